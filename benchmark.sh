@@ -1,21 +1,4 @@
 #!/bin/bash
+# Extra arguments are passed through, e.g. --headless or --report json.
 cargo build --release
-./target/release/gl_sandpit null
-./target/release/gl_sandpit clear1
-./target/release/gl_sandpit clear2
-./target/release/gl_sandpit clear4
-./target/release/gl_sandpit clear8
-./target/release/gl_sandpit quad1
-./target/release/gl_sandpit quad2
-./target/release/gl_sandpit quad4
-./target/release/gl_sandpit quad8
-./target/release/gl_sandpit clear1_quad1
-./target/release/gl_sandpit clear1_quad2
-./target/release/gl_sandpit clear1_quad3
-./target/release/gl_sandpit clear1_quad4
-./target/release/gl_sandpit clear1_quad5
-./target/release/gl_sandpit clear1_quad6
-./target/release/gl_sandpit clear1_quad7
-./target/release/gl_sandpit clear1_quad8
-./target/release/gl_sandpit clear1_quad9
-./target/release/gl_sandpit clear1_quad10
+./target/release/gl_sandpit suite "$@" null 'clear?' 'quad?' 'clear1_quad*'
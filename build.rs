@@ -0,0 +1,26 @@
+use std::fs;
+use std::process::Command;
+
+// Record the commit the binary was built from, for reports.
+fn main() {
+    let output = Command::new("git").args(["describe", "--always", "--dirty", "--abbrev=40"])
+                                    .output();
+    let commit = match output {
+        Ok(ref output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).trim().to_owned()
+        }
+        _ => "unknown".to_owned(),
+    };
+    println!("cargo:rustc-env=GIT_COMMIT={}", commit);
+
+    // Rerun for new commits and for edits that change the dirty state.
+    let mut watched = vec![".git/HEAD".to_owned(), ".git/index".to_owned(), "src".to_owned(), "res".to_owned()];
+    if let Ok(head) = fs::read_to_string(".git/HEAD") {
+        if let Some(reference) = head.trim().strip_prefix("ref: ") {
+            watched.push(format!(".git/{}", reference));
+        }
+    }
+    for path in watched {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+}
@@ -0,0 +1,21 @@
+#include "shared.glsl"
+
+#ifdef VERTEX_SHADER
+void main() {
+    vec4 rect = fetch_instance(2, 0);
+    vec4 uv_rect = fetch_instance(2, 1);
+    vec2 pos = mix(rect.xy, rect.xy + rect.zw, aPosition);
+
+    vUv = mix(uv_rect.xy, uv_rect.zw, aPosition);
+
+    gl_Position = vec4(uTransform.xy + pos * uTransform.zw, 0.0, 1.0);
+}
+#endif
+
+#ifdef FRAGMENT_SHADER
+uniform sampler2D sColor;
+
+void main() {
+    oFragColor = texture(sColor, vUv);
+}
+#endif
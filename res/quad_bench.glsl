@@ -0,0 +1,39 @@
+#include "shared.glsl"
+
+#ifdef VERTEX_SHADER
+#ifdef CLIP_RECTS
+    #define VECS_PER_INSTANCE 2
+#else
+    #define VECS_PER_INSTANCE 1
+#endif
+
+void main() {
+    vec4 data = fetch_instance(VECS_PER_INSTANCE, 0);
+    vec2 pos = mix(data.xy, data.xy + data.zw, aPosition);
+
+#ifdef CLIP_RECTS
+    init_clip(fetch_instance(VECS_PER_INSTANCE, 1), pos);
+#endif
+
+    vUv = aPosition;
+
+    gl_Position = vec4(uTransform.xy + pos * uTransform.zw, 0.0, 1.0);
+}
+#endif
+
+#ifdef FRAGMENT_SHADER
+void main() {
+#ifdef CLIP_RECTS
+    apply_clip();
+#endif
+
+    oFragColor = vec4(vUv, 0.0, 1.0);
+#ifdef TRANSLUCENT
+    // Premultiplied, at half opacity.
+    oFragColor *= 0.5;
+#endif
+#ifdef DUAL_SOURCE_BLENDING
+    oFragBlend = vec4(0.5);
+#endif
+}
+#endif
@@ -1,4 +1,4 @@
-#line 1
+#pragma once
 
 //======================================================================================
 // Vertex shader attributes and uniforms
@@ -9,8 +9,6 @@
 
     // Uniforms
     uniform vec4 uTransform;
-    uniform sampler2D sFloat0;
-    uniform sampler2D sFloat1;
 
     // Attribute inputs
     in vec2 aPosition;
@@ -28,6 +26,10 @@
 
     // Fragment shader outputs
     out vec4 oFragColor;
+    #ifdef DUAL_SOURCE_BLENDING
+        // The second source of dual source blend factors.
+        out vec4 oFragBlend;
+    #endif
 #endif
 
 //======================================================================================
@@ -36,6 +38,13 @@
 varying vec4 vColor;
 varying vec2 vUv;
 
+#ifdef CLIP_RECTS
+    // The clip rect's top left and bottom right corners, and the position
+    // being drawn, in pixels with the origin at the top left.
+    flat varying vec4 vClipRect;
+    varying vec2 vClipPos;
+#endif
+
 //======================================================================================
 // VS only types and UBOs
 //======================================================================================
@@ -62,6 +71,68 @@ vec4 fetch_vec4(int index, sampler2D s) {
     return texelFetchOffset(s, uv, 0, ivec2(0, 0));
 }
 
+ivec4 fetch_ivec4(int index, isampler2D s) {
+    ivec2 uv = get_fetch_uv(index, 1);
+    return texelFetchOffset(s, uv, 0, ivec2(0, 0));
+}
+
+//======================================================================================
+// Instance data
+//======================================================================================
+// Compile with one of the INSTANCES_* defines from InstanceBackend::define,
+// then read vec4 `offset` of the current instance with fetch_instance.
+// Draws of a range of instances set uFirstInstance to the first one.
+#if !defined(INSTANCES_ATTRIBUTES)
+    uniform int uFirstInstance;
+
+    int instance_index() {
+        return uFirstInstance + gl_InstanceID;
+    }
+#endif
+
+#if defined(INSTANCES_TEXTURE)
+    uniform sampler2D sInstances;
+
+    vec4 fetch_instance(int vecs_per_item, int offset) {
+        ivec2 uv = get_fetch_uv(instance_index(), vecs_per_item);
+        return texelFetch(sInstances, uv + ivec2(offset, 0), 0);
+    }
+#elif defined(INSTANCES_UBO)
+    #define MAX_INSTANCE_UNIFORM_VECS 1024
+
+    // Each draw binds a block of instances, so instance_index restarts at
+    // zero for each block.
+    layout(std140) uniform Instances {
+        vec4 uInstances[MAX_INSTANCE_UNIFORM_VECS];
+    };
+
+    vec4 fetch_instance(int vecs_per_item, int offset) {
+        return uInstances[instance_index() * vecs_per_item + offset];
+    }
+#elif defined(INSTANCES_TBO)
+    uniform samplerBuffer sInstances;
+
+    vec4 fetch_instance(int vecs_per_item, int offset) {
+        return texelFetch(sInstances, instance_index() * vecs_per_item + offset);
+    }
+#elif defined(INSTANCES_SSBO)
+    layout(std430) readonly buffer Instances {
+        vec4 bInstances[];
+    };
+
+    vec4 fetch_instance(int vecs_per_item, int offset) {
+        return bInstances[instance_index() * vecs_per_item + offset];
+    }
+#elif defined(INSTANCES_ATTRIBUTES)
+    #define MAX_INSTANCE_ATTRIBUTES 4
+
+    in vec4 aInstance[MAX_INSTANCE_ATTRIBUTES];
+
+    vec4 fetch_instance(int vecs_per_item, int offset) {
+        return aInstance[offset];
+    }
+#endif
+
 Rect fetch_rect(int index, sampler2D s) {
     ivec2 uv = get_fetch_uv(index, 1);
 
@@ -74,4 +145,30 @@ Rect fetch_rect(int index, sampler2D s) {
     return rect;
 }
 
+#ifdef CLIP_RECTS
+// Clip to `clip_rect`, given as (x, y, width, height) like instance rects.
+// `pos` is the vertex position before uTransform is applied.
+void init_clip(vec4 clip_rect, vec2 pos) {
+    vClipRect = vec4(clip_rect.xy, clip_rect.xy + clip_rect.zw);
+    vClipPos = pos;
+}
+#endif
+
+#endif
+
+//======================================================================================
+// FS only functions
+//======================================================================================
+#ifdef FRAGMENT_SHADER
+
+#ifdef CLIP_RECTS
+// Discard fragments outside the clip rect. Pixel centers on the edges are
+// treated like the scissor test does.
+void apply_clip() {
+    if (any(lessThan(vClipPos, vClipRect.xy)) || any(greaterThanEqual(vClipPos, vClipRect.zw))) {
+        discard;
+    }
+}
+#endif
+
 #endif
@@ -0,0 +1,22 @@
+#include "shared.glsl"
+
+#ifdef VERTEX_SHADER
+void main() {
+    vec4 rect = fetch_instance(1, 0);
+    vec2 pos = mix(rect.xy, rect.xy + rect.zw, aPosition);
+
+    vUv = aPosition;
+
+    gl_Position = vec4(uTransform.xy + pos * uTransform.zw, 0.0, 1.0);
+}
+#endif
+
+#ifdef FRAGMENT_SHADER
+// Premultiplied.
+uniform vec4 uColor;
+
+void main() {
+    // Shade across the quad, so that flips and offsets show.
+    oFragColor = vec4(uColor.rgb * mix(0.5, 1.0, vUv.x * vUv.y), uColor.a);
+}
+#endif
@@ -1,6 +1,10 @@
+#include "shared.glsl"
+
 #ifdef VERTEX_SHADER
+uniform sampler2D sRects;
+
 void main() {
-    Rect rect = fetch_rect(gl_InstanceID, sFloat0);
+    Rect rect = fetch_rect(gl_InstanceID, sRects);
     vec2 pos = mix(rect.p0,
                    rect.p1,
                    aPosition);
@@ -1,6 +1,37 @@
 use gfx::GfxContext;
+use glutin;
 
+pub type KeyCode = glutin::VirtualKeyCode;
+pub type MouseButton = glutin::MouseButton;
+
+/// Modifier keys held when an event was generated.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ScrollDelta {
+    Lines(f32, f32),
+    Pixels(f32, f32),
+}
+
+/// Input and window events, in window coordinates with the origin top left.
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Event {
+    KeyDown { key: Option<KeyCode>, scancode: u8, modifiers: Modifiers },
+    KeyUp { key: Option<KeyCode>, scancode: u8, modifiers: Modifiers },
+    Character(char),
+    MouseMove { x: i32, y: i32 },
+    MouseDown { button: MouseButton, x: i32, y: i32, modifiers: Modifiers },
+    MouseUp { button: MouseButton, x: i32, y: i32, modifiers: Modifiers },
+    MouseWheel { delta: ScrollDelta, modifiers: Modifiers },
+    Resize { width: u32, height: u32 },
+    Focus(bool),
+    ScaleFactor(f32),
 }
 
 #[allow(dead_code)]
@@ -22,6 +53,13 @@ pub trait App {
             width: u32,
             height: u32);
 
+    /// Named values describing the run, such as how resources were used,
+    /// which are added to the app's record in reports. Called after the
+    /// last frame, before `deinit`.
+    fn metadata(&self) -> Vec<(String, f64)> {
+        Vec::new()
+    }
+
     fn deinit(&mut self, _: &mut GfxContext) {
     }
 }
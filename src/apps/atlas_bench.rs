@@ -0,0 +1,251 @@
+use app::{App, AppKind};
+use atlas::{AtlasId, TextureAtlas};
+use gfx::{GfxContext, ProgramId, TextureId};
+use instances::{InstanceBackend, InstanceBuffer};
+use registry::{AppDesc, AppRegistry, ParamDesc, ParamKind, Params};
+use std::mem;
+use texture::{TextureFilter, TextureOptions};
+use types::ColorF;
+
+const MAX_IMAGE_SIZE: u32 = 64;
+
+// Space between images on screen.
+const CELL_SIZE: u32 = MAX_IMAGE_SIZE + 2;
+
+pub fn register(registry: &mut AppRegistry) {
+    registry.register(AppDesc {
+        name: "atlas",
+        description: "Draws many small generated images, packed in an atlas or each in its own texture",
+        patterns: &["atlas{images}", "atlas{images}_{mode}", "atlas{images}_churn{churn}"],
+        presets: &["atlas100", "atlas100_separate", "atlas1000", "atlas1000_separate", "atlas1000_churn50"],
+        params: &[
+            ParamDesc {
+                name: "images",
+                kind: ParamKind::UInt,
+                default: "1000",
+                description: "Number of images",
+            },
+            ParamDesc {
+                name: "mode",
+                kind: ParamKind::Choice(&["atlas", "separate"]),
+                default: "atlas",
+                description: "Draw from atlas pages, or draw each image with its own texture",
+            },
+            ParamDesc {
+                name: "churn",
+                kind: ParamKind::UInt,
+                default: "0",
+                description: "Images replaced by new ones of another size each frame",
+            },
+            ParamDesc {
+                name: "page_size",
+                kind: ParamKind::UInt,
+                default: "1024",
+                description: "Width and height of atlas pages",
+            },
+        ],
+        create,
+    });
+}
+
+fn create(gfx: &mut GfxContext, params: &Params) -> Result<Box<dyn App>, String> {
+    let atlas = match params.get_choice("mode") {
+        "atlas" => Some(gfx.create_texture_atlas(params.get_uint("page_size") as u32, TextureFilter::Linear)?),
+        _ => None,
+    };
+    Ok(Box::new(AtlasBench::new(gfx, params.get_uint("images"), params.get_uint("churn"), atlas)?))
+}
+
+enum Images {
+    Atlas(TextureAtlas, Vec<AtlasId>),
+    Separate(Vec<TextureId>),
+}
+
+pub struct AtlasBench {
+    program: ProgramId,
+    instances: InstanceBuffer,
+    images: Images,
+    churn: usize,
+    next_churn: usize,
+    rng: u32,
+}
+
+impl AtlasBench {
+    /// Images are packed into `atlas` if there is one.
+    pub fn new(gfx: &mut GfxContext,
+               count: usize,
+               churn: usize,
+               atlas: Option<TextureAtlas>) -> Result<AtlasBench, String> {
+        let backend = InstanceBackend::Texture;
+        let mut bench = AtlasBench {
+            program: gfx.create_program("image.glsl", &[backend.define()])?,
+            instances: gfx.create_instance_buffer(backend, mem::size_of::<[[f32; 4]; 2]>())?,
+            images: match atlas {
+                Some(atlas) => Images::Atlas(atlas, Vec::new()),
+                None => Images::Separate(Vec::new()),
+            },
+            churn,
+            next_churn: 0,
+            rng: 0x2545f491,
+        };
+        for _ in 0..count {
+            bench.add_image(gfx, None)?;
+        }
+        Ok(bench)
+    }
+
+    fn random(&mut self, max: u32) -> u32 {
+        // xorshift32
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng % max
+    }
+
+    // A random sized, solid colored image with a dark border, so that
+    // bleeding between atlas entries shows.
+    fn generate_image(&mut self) -> (u32, u32, Vec<u8>) {
+        let width = 4 + self.random(MAX_IMAGE_SIZE - 3);
+        let height = 4 + self.random(MAX_IMAGE_SIZE - 3);
+        let color = [self.random(256) as u8, self.random(256) as u8, self.random(256) as u8, 255];
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                    pixels.extend_from_slice(&[0, 0, 0, 255]);
+                } else {
+                    pixels.extend_from_slice(&color);
+                }
+            }
+        }
+        (width, height, pixels)
+    }
+
+    // Add a new image, in place of image `index` if given.
+    fn add_image(&mut self, gfx: &mut GfxContext, index: Option<usize>) -> Result<(), String> {
+        let (width, height, pixels) = self.generate_image();
+        match self.images {
+            Images::Atlas(ref mut atlas, ref mut ids) => {
+                let id = atlas.add(width, height, &pixels)?;
+                match index {
+                    Some(index) => {
+                        atlas.remove(ids[index]);
+                        ids[index] = id;
+                    }
+                    None => ids.push(id),
+                }
+            }
+            Images::Separate(ref mut textures) => {
+                let id = gfx.create_texture_from_rgba8(width, height, &pixels, TextureOptions::default())?;
+                match index {
+                    Some(index) => {
+                        gfx.destroy_texture(textures[index]);
+                        textures[index] = id;
+                    }
+                    None => textures.push(id),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn image_count(&self) -> usize {
+        match self.images {
+            Images::Atlas(_, ref ids) => ids.len(),
+            Images::Separate(ref textures) => textures.len(),
+        }
+    }
+}
+
+impl App for AtlasBench {
+    fn kind(&self) -> AppKind {
+        AppKind::Benchmark
+    }
+
+    fn draw(&mut self,
+            gfx: &mut GfxContext,
+            width: u32,
+            _: u32) {
+        let count = self.image_count();
+        if count > 0 {
+            for _ in 0..self.churn {
+                let index = self.next_churn % count;
+                self.next_churn += 1;
+                if let Err(e) = self.add_image(gfx, Some(index)) {
+                    eprintln!("Failed to replace image: {}", e);
+                }
+            }
+        }
+
+        gfx.clear(ColorF::new(0.5, 0.5, 0.5, 1.0));
+        gfx.bind_program(self.program);
+
+        // Images are drawn at their own size, in a grid of cells.
+        let columns = (width / CELL_SIZE).max(1) as usize;
+        let cell_rect = |index: usize, width: i32, height: i32| {
+            let x = (index % columns) as u32 * CELL_SIZE;
+            let y = (index / columns) as u32 * CELL_SIZE;
+            [x as f32, y as f32, width as f32, height as f32]
+        };
+
+        match self.images {
+            Images::Atlas(ref atlas, ref ids) => {
+                // One draw for each page.
+                for page in 0..atlas.page_count() {
+                    let instances: Vec<[[f32; 4]; 2]> = ids.iter().enumerate().filter_map(|(index, &id)| {
+                        let entry = atlas.entry(id);
+                        if entry.page == page {
+                            Some([cell_rect(index, entry.rect.width, entry.rect.height), entry.uv_rect])
+                        } else {
+                            None
+                        }
+                    }).collect();
+                    if instances.is_empty() {
+                        continue;
+                    }
+                    gfx.bind_instances(&mut self.instances, &instances);
+                    gfx.bind_texture("sColor", atlas.page_binding(page));
+                    gfx.draw_quads(instances.len());
+                }
+            }
+            Images::Separate(ref textures) => {
+                // One upload, then a draw for each texture.
+                let instances: Vec<[[f32; 4]; 2]> = textures.iter().enumerate().map(|(index, &texture)| {
+                    let (width, height) = gfx.texture_size(texture);
+                    [cell_rect(index, width as i32, height as i32), [0.0, 0.0, 1.0, 1.0]]
+                }).collect();
+                gfx.bind_instances(&mut self.instances, &instances);
+                for (index, &texture) in textures.iter().enumerate() {
+                    let binding = gfx.texture_binding(texture);
+                    gfx.bind_texture("sColor", binding);
+                    gfx.draw_quads_range(index, 1);
+                }
+            }
+        }
+    }
+
+    fn metadata(&self) -> Vec<(String, f64)> {
+        match self.images {
+            Images::Atlas(ref atlas, _) => {
+                let stats = atlas.stats();
+                let total_area = stats.allocated_area + stats.free_area;
+                vec![
+                    ("atlas_images".to_owned(), stats.entries as f64),
+                    ("atlas_pages".to_owned(), stats.pages as f64),
+                    ("atlas_allocated".to_owned(), stats.allocated_area as f64 / total_area.max(1) as f64),
+                    ("atlas_fragmentation".to_owned(), stats.fragmentation as f64),
+                    ("atlas_largest_free_area".to_owned(), stats.largest_free_area as f64),
+                ]
+            }
+            Images::Separate(ref textures) => vec![("textures".to_owned(), textures.len() as f64)],
+        }
+    }
+
+    fn deinit(&mut self, gfx: &mut GfxContext) {
+        if let Images::Separate(ref mut textures) = self.images {
+            for texture in textures.drain(..) {
+                gfx.destroy_texture(texture);
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use app::{App, AppKind};
+use gfx::{GfxContext, ProgramId, TextureId};
+use instances::{InstanceBackend, InstanceBuffer};
+use pipeline::{BlendState, PipelineState};
+use registry::{AppDesc, AppRegistry, ParamDesc, ParamKind, Params};
+use std::mem;
+use texture::{TextureFilter, TextureOptions, TextureWrap};
+use types::ColorF;
+
+const IMAGE_PATH: &str = "images/quadrants.png";
+
+pub fn register(registry: &mut AppRegistry) {
+    registry.register(AppDesc {
+        name: "image",
+        description: "Draws a grid of textured quads from res/images/quadrants.png",
+        patterns: &["image{count}", "image{count}_{filter}"],
+        presets: &["image1", "image100", "image100_nearest", "image100_trilinear"],
+        params: &[
+            ParamDesc {
+                name: "count",
+                kind: ParamKind::UInt,
+                default: "16",
+                description: "Number of quads",
+            },
+            ParamDesc {
+                name: "filter",
+                kind: ParamKind::Choice(&["nearest", "linear", "trilinear"]),
+                default: "linear",
+                description: "Texture filtering",
+            },
+            ParamDesc {
+                name: "wrap",
+                kind: ParamKind::Choice(&["clamp", "repeat", "mirror"]),
+                default: "clamp",
+                description: "How UVs outside the image are wrapped",
+            },
+            ParamDesc {
+                name: "uv_scale",
+                kind: ParamKind::Float,
+                default: "1",
+                description: "Size of each quad's UV rect, so values above 1 show the wrap mode",
+            },
+        ],
+        create,
+    });
+}
+
+fn create(gfx: &mut GfxContext, params: &Params) -> Result<Box<dyn App>, String> {
+    let filter = match params.get_choice("filter") {
+        "nearest" => TextureFilter::Nearest,
+        "trilinear" => TextureFilter::Trilinear,
+        _ => TextureFilter::Linear,
+    };
+    let wrap = match params.get_choice("wrap") {
+        "repeat" => TextureWrap::Repeat,
+        "mirror" => TextureWrap::MirroredRepeat,
+        _ => TextureWrap::Clamp,
+    };
+    let options = TextureOptions {
+        filter,
+        wrap,
+    };
+    Ok(Box::new(Image::new(gfx, params.get_uint("count"), options, params.get_float("uv_scale"))?))
+}
+
+pub struct Image {
+    program: ProgramId,
+    texture: TextureId,
+    instances: InstanceBuffer,
+    count: usize,
+    uv_scale: f32,
+}
+
+impl Image {
+    pub fn new(gfx: &mut GfxContext,
+               count: usize,
+               options: TextureOptions,
+               uv_scale: f32) -> Result<Image, String> {
+        let backend = InstanceBackend::Texture;
+        Ok(Image {
+            program: gfx.create_program("image.glsl", &[backend.define()])?,
+            texture: gfx.create_texture_from_file(IMAGE_PATH, options)?,
+            instances: gfx.create_instance_buffer(backend, mem::size_of::<[[f32; 4]; 2]>())?,
+            count,
+            uv_scale,
+        })
+    }
+}
+
+impl App for Image {
+    fn kind(&self) -> AppKind {
+        AppKind::Benchmark
+    }
+
+    fn draw(&mut self,
+            gfx: &mut GfxContext,
+            width: u32,
+            height: u32) {
+        gfx.clear(ColorF::new(0.5, 0.5, 0.5, 1.0));
+        if self.count == 0 {
+            return;
+        }
+
+        // Lay the quads out in a grid of square cells that fits the screen.
+        let columns = (self.count as f32).sqrt().ceil() as usize;
+        let rows = self.count.div_ceil(columns);
+        let cell = (width as f32 / columns as f32).min(height as f32 / rows as f32);
+
+        let uv_rect = [0.0, 0.0, self.uv_scale, self.uv_scale];
+        let instances: Vec<[[f32; 4]; 2]> = (0..self.count).map(|i| {
+            let x = (i % columns) as f32 * cell;
+            let y = (i / columns) as f32 * cell;
+            [[x, y, cell, cell], uv_rect]
+        }).collect();
+
+        gfx.bind_instances(&mut self.instances, &instances);
+        let binding = gfx.texture_binding(self.texture);
+        gfx.bind_texture("sColor", binding);
+        gfx.bind_program_with_state(self.program, &PipelineState::blended(BlendState::premultiplied_alpha()));
+        gfx.draw_quads(instances.len());
+    }
+}
@@ -1,2 +1,15 @@
+use registry::AppRegistry;
+
+pub mod atlas_bench;
+pub mod image;
+pub mod render_target;
 pub mod test;
 pub mod quad_bench;
+
+pub fn register_all(registry: &mut AppRegistry) {
+    test::register(registry);
+    quad_bench::register(registry);
+    image::register(registry);
+    atlas_bench::register(registry);
+    render_target::register(registry);
+}
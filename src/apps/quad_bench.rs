@@ -1,31 +1,173 @@
 use app::{App, AppKind};
-use gfx::{GfxContext, ProgramId, VertexDataTexture, TextureSampler, VertexTextureFormat};
-use gleam::gl;
-use types::ColorF;
+use gfx::{GfxContext, ProgramId};
+use instances::{InstanceBackend, InstanceBuffer};
+use pipeline::{BlendState, CompareFunc, PipelineState, StencilOp, StencilState};
+use registry::{AppDesc, AppRegistry, ParamDesc, ParamKind, Params};
+use std::mem;
+use types::{ColorF, Rect};
+
+pub fn register(registry: &mut AppRegistry) {
+    registry.register(AppDesc {
+        name: "quad_bench",
+        description: "Clears and draws full screen quads each frame",
+        patterns: &[
+            "null", "clear{clear}", "quad{quads}", "clear{clear}_quad{quads}",
+            "quad{quads}_{instances}", "clear{clear}_quad{quads}_{instances}",
+            "quad{quads}_blend_{blend}", "quad{quads}_clip_{clip}",
+        ],
+        presets: &[
+            "null",
+            "clear1", "clear2", "clear4", "clear8",
+            "quad1", "quad2", "quad4", "quad8",
+            "clear1_quad1", "clear1_quad2", "clear1_quad3", "clear1_quad4", "clear1_quad5",
+            "clear1_quad6", "clear1_quad7", "clear1_quad8", "clear1_quad9", "clear1_quad10",
+            "quad1000_texture", "quad1000_ubo", "quad1000_tbo", "quad1000_ssbo", "quad1000_attributes",
+            "quad10000_texture", "quad10000_ubo", "quad10000_tbo", "quad10000_ssbo", "quad10000_attributes",
+            "quad8_blend_premultiplied", "quad8_blend_additive", "quad8_blend_dual_source",
+            "quad8_clip_scissor", "quad8_clip_shader", "quad8_clip_stencil",
+        ],
+        params: &[
+            ParamDesc {
+                name: "clear",
+                kind: ParamKind::UInt,
+                default: "0",
+                description: "Number of full screen clears",
+            },
+            ParamDesc {
+                name: "quads",
+                kind: ParamKind::UInt,
+                default: "0",
+                description: "Number of full screen quads",
+            },
+            ParamDesc {
+                name: "instances",
+                kind: ParamKind::Choice(InstanceBackend::NAMES),
+                default: "texture",
+                description: "How instance data reaches the vertex shader",
+            },
+            ParamDesc {
+                name: "blend",
+                kind: ParamKind::Choice(BLEND_MODES),
+                default: "opaque",
+                description: "How quads are blended, drawing them at half opacity if blended",
+            },
+            ParamDesc {
+                name: "clip",
+                kind: ParamKind::Choice(CLIP_MODES),
+                default: "none",
+                description: "Clip quads to the middle of the screen with the scissor rect, in the shader or with a stencil mask",
+            },
+        ],
+        create,
+    });
+}
+
+const BLEND_MODES: &[&str] = &["opaque", "premultiplied", "additive", "dual_source"];
+
+fn blend_state(mode: &str) -> Option<BlendState> {
+    match mode {
+        "premultiplied" => Some(BlendState::premultiplied_alpha()),
+        "additive" => Some(BlendState::additive()),
+        "dual_source" => Some(BlendState::dual_source()),
+        _ => None,
+    }
+}
+
+const CLIP_MODES: &[&str] = &["none", "scissor", "shader", "stencil"];
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Clip {
+    None,
+    Scissor,
+    Shader,
+    /// Draw the clip rect into the stencil buffer, then draw quads where
+    /// it was written.
+    Stencil,
+}
+
+impl Clip {
+    fn from_name(name: &str) -> Clip {
+        match name {
+            "scissor" => Clip::Scissor,
+            "shader" => Clip::Shader,
+            "stencil" => Clip::Stencil,
+            _ => Clip::None,
+        }
+    }
+}
+
+fn create(gfx: &mut GfxContext, params: &Params) -> Result<Box<dyn App>, String> {
+    let backend = InstanceBackend::from_name(params.get_choice("instances")).unwrap();
+    Ok(Box::new(QuadBench::new(gfx,
+                               params.get_uint("clear"),
+                               params.get_uint("quads"),
+                               backend,
+                               blend_state(params.get_choice("blend")),
+                               Clip::from_name(params.get_choice("clip")))?))
+}
 
 pub struct QuadBench {
     program: ProgramId,
-    instances: VertexDataTexture,
+    instances: InstanceBuffer,
     clear_count: usize,
     quad_count: usize,
+    state: PipelineState,
+    clip: Clip,
+}
+
+// Writes 1 wherever the mask is drawn, without touching the colors.
+fn stencil_mask_state() -> PipelineState {
+    PipelineState {
+        stencil: Some(StencilState {
+            reference: 1,
+            pass: StencilOp::Replace,
+            ..StencilState::write_all()
+        }),
+        color_mask: [false; 4],
+        ..PipelineState::default()
+    }
+}
+
+// Passes where the mask was drawn.
+fn stencil_test() -> StencilState {
+    StencilState {
+        func: CompareFunc::Equal,
+        reference: 1,
+        write_mask: 0,
+        ..StencilState::write_all()
+    }
 }
 
 impl QuadBench {
     pub fn new(gfx: &mut GfxContext,
                clear_count: usize,
-               quad_count: usize) -> QuadBench {
-        if clear_count == 0 && quad_count == 0 {
-            println!("{}", gl::get_string(gl::RENDERER));
-            println!("{}", gl::get_string(gl::VERSION));
-            println!("{}", gl::get_string(gl::VENDOR));
+               quad_count: usize,
+               backend: InstanceBackend,
+               blend: Option<BlendState>,
+               clip: Clip) -> Result<QuadBench, String> {
+        let mut defines = vec![backend.define()];
+        if let Some(blend) = blend {
+            defines.push("TRANSLUCENT");
+            if blend == BlendState::dual_source() {
+                defines.push("DUAL_SOURCE_BLENDING");
+            }
         }
-
-        QuadBench {
-            clear_count: clear_count,
-            quad_count: quad_count,
-            program: gfx.create_program("test.glsl"),
-            instances: gfx.create_vertex_texture(TextureSampler::Float0),
+        if clip == Clip::Shader {
+            defines.push("CLIP_RECTS");
         }
+
+        Ok(QuadBench {
+            clear_count,
+            quad_count,
+            instances: gfx.create_instance_buffer(backend, mem::size_of::<[[f32; 4]; 2]>())?,
+            program: gfx.create_program("quad_bench.glsl", &defines)?,
+            state: PipelineState {
+                blend,
+                stencil: if clip == Clip::Stencil { Some(stencil_test()) } else { None },
+                ..PipelineState::default()
+            },
+            clip,
+        })
     }
 }
 
@@ -38,20 +180,60 @@ impl App for QuadBench {
             gfx: &mut GfxContext,
             width: u32,
             height: u32) {
-        for _ in 0..self.clear_count {
-            gfx.clear(ColorF::new(0.0, 0.0, 0.0, 1.0));
+        if self.clear_count > 0 {
+            gfx.begin_gpu_scope("clear");
+            for _ in 0..self.clear_count {
+                gfx.clear(ColorF::new(0.0, 0.0, 0.0, 1.0));
+            }
+            gfx.end_gpu_scope();
         }
 
         if self.quad_count > 0 {
-            let mut instances: Vec<[f32; 4]> = Vec::new();
+            gfx.begin_gpu_scope("quads");
+            let rect = [0.0, 0.0, width as f32, height as f32];
+            let clip_rect = Rect::new(width as i32 / 4, height as i32 / 4, width as i32 / 2, height as i32 / 2);
+
+            let clip = [clip_rect.x as f32, clip_rect.y as f32, clip_rect.width as f32, clip_rect.height as f32];
 
-            for _ in 0..self.quad_count {
-                instances.push([0.0, 0.0, width as f32, height as f32]);
+            gfx.begin_gpu_scope("upload");
+            match self.clip {
+                Clip::Shader => {
+                    let instances = vec![[rect, clip]; self.quad_count];
+                    gfx.bind_instances(&mut self.instances, &instances);
+                }
+                // The mask is drawn from the first instance.
+                Clip::Stencil => {
+                    let mut instances = vec![rect; self.quad_count + 1];
+                    instances[0] = clip;
+                    gfx.bind_instances(&mut self.instances, &instances);
+                }
+                _ => {
+                    let instances = vec![rect; self.quad_count];
+                    gfx.bind_instances(&mut self.instances, &instances);
+                }
             }
+            gfx.end_gpu_scope();
 
-            gfx.bind_vertex_texture(&self.instances, &mut instances, VertexTextureFormat::F32);
-            gfx.bind_program(self.program);
-            gfx.draw_quads(instances.len());
+            match self.clip {
+                Clip::Scissor => {
+                    gfx.push_clip_rect(clip_rect);
+                    gfx.bind_program_with_state(self.program, &self.state);
+                    gfx.draw_quads(self.quad_count);
+                    gfx.pop_clip_rect();
+                }
+                Clip::Stencil => {
+                    gfx.clear_depth_stencil(1.0, 0);
+                    gfx.bind_program_with_state(self.program, &stencil_mask_state());
+                    gfx.draw_quads(1);
+                    gfx.bind_program_with_state(self.program, &self.state);
+                    gfx.draw_quads_range(1, self.quad_count);
+                }
+                _ => {
+                    gfx.bind_program_with_state(self.program, &self.state);
+                    gfx.draw_quads(self.quad_count);
+                }
+            }
+            gfx.end_gpu_scope();
         }
     }
 }
\ No newline at end of file
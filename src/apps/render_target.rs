@@ -0,0 +1,145 @@
+use app::App;
+use gfx::{GfxContext, ProgramId, RenderTargetId};
+use instances::{InstanceBackend, InstanceBuffer};
+use pipeline::{BlendState, PipelineState};
+use registry::{AppDesc, AppRegistry, ParamDesc, ParamKind, Params};
+use render_target::RenderTargetFormat;
+use std::mem;
+use types::{ColorF, Rect};
+
+pub fn register(registry: &mut AppRegistry) {
+    registry.register(AppDesc {
+        name: "render_target",
+        description: "Draws a scene directly, or into a render target that is then sampled or blitted to the screen",
+        patterns: &["render_target_{mode}"],
+        presets: &["render_target_direct", "render_target_sample", "render_target_blit"],
+        params: &[
+            ParamDesc {
+                name: "mode",
+                kind: ParamKind::Choice(MODES),
+                default: "sample",
+                description: "How the scene reaches the screen. Every mode draws the same image, \
+                              so render_target_direct is the reference for the others",
+            },
+        ],
+        create,
+    });
+}
+
+const MODES: &[&str] = &["direct", "sample", "blit"];
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Mode {
+    Direct,
+    Sample,
+    Blit,
+}
+
+fn create(gfx: &mut GfxContext, params: &Params) -> Result<Box<dyn App>, String> {
+    let mode = match params.get_choice("mode") {
+        "direct" => Mode::Direct,
+        "blit" => Mode::Blit,
+        _ => Mode::Sample,
+    };
+    Ok(Box::new(RenderTargetTest::new(gfx, mode)?))
+}
+
+pub struct RenderTargetTest {
+    mode: Mode,
+    solid_program: ProgramId,
+    image_program: ProgramId,
+    instances: InstanceBuffer,
+    // Created at the size of the first frame, and resized to follow it.
+    target: Option<RenderTargetId>,
+}
+
+impl RenderTargetTest {
+    pub fn new(gfx: &mut GfxContext, mode: Mode) -> Result<RenderTargetTest, String> {
+        let backend = InstanceBackend::Texture;
+        Ok(RenderTargetTest {
+            mode,
+            solid_program: gfx.create_program("solid.glsl", &[backend.define()])?,
+            image_program: gfx.create_program("image.glsl", &[backend.define()])?,
+            instances: gfx.create_instance_buffer(backend, mem::size_of::<[[f32; 4]; 2]>())?,
+            target: None,
+        })
+    }
+
+    // Two opaque quads, with a translucent one overlapping both.
+    fn draw_scene(&mut self, gfx: &mut GfxContext, width: u32, height: u32) {
+        let (w, h) = (width as f32, height as f32);
+        let instances: Vec<[f32; 4]> = vec![
+            [w * 0.1, h * 0.1, w * 0.4, h * 0.5],
+            [w * 0.6, h * 0.2, w * 0.3, h * 0.6],
+            [w * 0.3, h * 0.4, w * 0.5, h * 0.4],
+        ];
+
+        gfx.clear(ColorF::new(0.1, 0.1, 0.3, 1.0));
+        gfx.bind_instances(&mut self.instances, &instances);
+        gfx.bind_program(self.solid_program);
+        gfx.set_uniform(self.solid_program, "uColor", ColorF::new(0.9, 0.4, 0.1, 1.0));
+        gfx.draw_quads(2);
+        gfx.bind_program_with_state(self.solid_program, &PipelineState::blended(BlendState::premultiplied_alpha()));
+        gfx.set_uniform(self.solid_program, "uColor", ColorF::new(0.0, 0.4, 0.2, 0.5));
+        gfx.draw_quads_range(2, 1);
+    }
+
+    fn target(&mut self, gfx: &mut GfxContext, width: u32, height: u32) -> Result<RenderTargetId, String> {
+        match self.target {
+            Some(target) => {
+                if gfx.render_target_size(target) != (width, height) {
+                    gfx.resize_render_target(target, width, height)?;
+                }
+                Ok(target)
+            }
+            None => {
+                let target = gfx.create_render_target(width, height, RenderTargetFormat::Rgba8)?;
+                self.target = Some(target);
+                Ok(target)
+            }
+        }
+    }
+}
+
+impl App for RenderTargetTest {
+    fn draw(&mut self,
+            gfx: &mut GfxContext,
+            width: u32,
+            height: u32) {
+        if self.mode == Mode::Direct {
+            self.draw_scene(gfx, width, height);
+            return;
+        }
+
+        let target = match self.target(gfx, width, height) {
+            Ok(target) => target,
+            Err(e) => {
+                eprintln!("Failed to create render target: {}", e);
+                return;
+            }
+        };
+        gfx.bind_render_target(Some(target));
+        self.draw_scene(gfx, width, height);
+        gfx.bind_render_target(None);
+
+        let rect = Rect::from_size(width, height);
+        match self.mode {
+            Mode::Sample => {
+                // Targets are stored bottom up, so the UV rect is flipped.
+                let instance = [[0.0, 0.0, width as f32, height as f32], [0.0, 1.0, 1.0, 0.0]];
+                gfx.bind_instances(&mut self.instances, &[instance]);
+                let binding = gfx.render_target_binding(target);
+                gfx.bind_texture("sColor", binding);
+                gfx.bind_program(self.image_program);
+                gfx.draw_quads(1);
+            }
+            _ => gfx.blit(Some(target), rect, None, rect),
+        }
+    }
+
+    fn deinit(&mut self, gfx: &mut GfxContext) {
+        if let Some(target) = self.target.take() {
+            gfx.destroy_render_target(target);
+        }
+    }
+}
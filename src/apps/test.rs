@@ -1,33 +1,59 @@
-use app::App;
-use gfx::{GfxContext, ProgramId, VertexDataTexture, TextureSampler, VertexTextureFormat};
+use app::{App, Event};
+use gfx::{GfxContext, ProgramId, VertexDataTexture, VertexTextureFormat};
+use registry::{AppDesc, AppRegistry, Params};
+use shader_error::ShaderError;
 use types::ColorF;
 
+pub fn register(registry: &mut AppRegistry) {
+    registry.register(AppDesc {
+        name: "test",
+        description: "Draws a couple of quads with res/test.glsl",
+        patterns: &[],
+        presets: &[],
+        params: &[],
+        create,
+    });
+}
+
+fn create(gfx: &mut GfxContext, _: &Params) -> Result<Box<dyn App>, String> {
+    Ok(Box::new(Test::new(gfx)?))
+}
+
 pub struct Test {
     program: ProgramId,
     instances: VertexDataTexture,
+    origin: [f32; 2],
 }
 
 impl Test {
-    pub fn new(gfx: &mut GfxContext) -> Test {
-        Test {
-            program: gfx.create_program("test.glsl"),
-            instances: gfx.create_vertex_texture(TextureSampler::Float0),
-        }
+    pub fn new(gfx: &mut GfxContext) -> Result<Test, ShaderError> {
+        Ok(Test {
+            program: gfx.create_program("test.glsl", &[])?,
+            instances: gfx.create_vertex_texture(VertexTextureFormat::F32),
+            origin: [100.0, 100.0],
+        })
     }
 }
 
 impl App for Test {
+    fn on_event(&mut self, event: Event) {
+        // The first quad follows the mouse.
+        if let Event::MouseMove { x, y } = event {
+            self.origin = [x as f32, y as f32];
+        }
+    }
+
     fn draw(&mut self,
             gfx: &mut GfxContext,
             _: u32,
             _: u32) {
-        let mut instances: Vec<[f32; 4]> = vec![
-            [100.0, 100.0, 100.0, 100.0],
+        let instances: Vec<[f32; 4]> = vec![
+            [self.origin[0], self.origin[1], 100.0, 100.0],
             [100.0, 300.0, 200.0, 50.0],
         ];
 
         gfx.clear(ColorF::new(0.0, 0.0, 0.0, 1.0));
-        gfx.bind_vertex_texture(&self.instances, &mut instances, VertexTextureFormat::F32);
+        gfx.bind_vertex_texture("sRects", &mut self.instances, &instances);
         gfx.bind_program(self.program);
         gfx.draw_quads(instances.len());
     }
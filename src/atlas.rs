@@ -0,0 +1,513 @@
+use gfx::TextureBinding;
+use gleam::gl;
+use reflection::SamplerKind;
+use texture::{Texture, TextureFilter, TextureOptions, TextureWrap};
+use types::Rect;
+
+// Shelf heights are rounded up to this, so that images of similar heights
+// share shelves.
+const SHELF_ALIGNMENT: i32 = 8;
+
+// Entries are surrounded by a copy of their edge pixels, so that linear
+// filtering at their edges doesn't blend in their neighbours.
+const GUTTER: i32 = 1;
+
+// A run of pixels along a shelf, either allocated or free.
+#[derive(Debug, Copy, Clone)]
+struct Span {
+    x: i32,
+    width: i32,
+    allocated: bool,
+}
+
+// A horizontal strip of the atlas. Its spans cover its whole width.
+#[derive(Debug, Clone)]
+struct Shelf {
+    y: i32,
+    height: i32,
+    spans: Vec<Span>,
+}
+
+impl Shelf {
+    fn new(y: i32, height: i32, width: i32) -> Shelf {
+        Shelf {
+            y,
+            height,
+            spans: vec![Span {
+                x: 0,
+                width,
+                allocated: false,
+            }],
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.spans.iter().all(|span| !span.allocated)
+    }
+
+    fn free_span(&self, width: i32) -> Option<usize> {
+        self.spans.iter().position(|span| !span.allocated && span.width >= width)
+    }
+}
+
+/// Packs rectangles into rows of shelves. Freed space is merged with free
+/// neighbours on its shelf, and empty shelves with empty neighbours, so
+/// that it can be reused by rectangles of other heights.
+pub struct ShelfAllocator {
+    width: i32,
+    height: i32,
+    // Ordered from the top, covering the whole height.
+    shelves: Vec<Shelf>,
+    allocated_area: i64,
+}
+
+impl ShelfAllocator {
+    pub fn new(width: i32, height: i32) -> ShelfAllocator {
+        ShelfAllocator {
+            width,
+            height,
+            shelves: vec![Shelf::new(0, height, width)],
+            allocated_area: 0,
+        }
+    }
+
+    /// Fails if the rectangle is empty or larger than the allocator, or if
+    /// there is no free space that fits it.
+    pub fn allocate(&mut self, width: i32, height: i32) -> Result<Rect, String> {
+        if width <= 0 || height <= 0 || width > self.width || height > self.height {
+            return Err(format!("{}x{} rectangle can't be allocated from {}x{}",
+                               width,
+                               height,
+                               self.width,
+                               self.height));
+        }
+
+        // Prefer a shelf in use that wastes little height, then starting a
+        // new shelf, then any shelf in use that is tall enough.
+        let shelf_height = (height + SHELF_ALIGNMENT - 1) / SHELF_ALIGNMENT * SHELF_ALIGNMENT;
+        let index = self.find_used_shelf(width, height, shelf_height)
+                        .or_else(|| self.split_empty_shelf(height, shelf_height))
+                        .or_else(|| self.find_used_shelf(width, height, self.height))
+                        .ok_or_else(|| format!("no free space for a {}x{} rectangle", width, height))?;
+
+        let shelf = &mut self.shelves[index];
+        let span_index = shelf.free_span(width).expect("shelf has no span that fits");
+        let span = shelf.spans[span_index];
+        if span.width > width {
+            shelf.spans.insert(span_index + 1, Span {
+                x: span.x + width,
+                width: span.width - width,
+                allocated: false,
+            });
+        }
+        shelf.spans[span_index] = Span {
+            x: span.x,
+            width,
+            allocated: true,
+        };
+
+        self.allocated_area += width as i64 * height as i64;
+        Ok(Rect::new(span.x, shelf.y, width, height))
+    }
+
+    // The shortest shelf in use that fits the rectangle and is no taller
+    // than `max_height`.
+    fn find_used_shelf(&self, width: i32, height: i32, max_height: i32) -> Option<usize> {
+        self.shelves.iter()
+                    .enumerate()
+                    .filter(|&(_, shelf)| {
+                        !shelf.is_empty() && shelf.height >= height && shelf.height <= max_height &&
+                        shelf.free_span(width).is_some()
+                    })
+                    .min_by_key(|&(_, shelf)| shelf.height)
+                    .map(|(index, _)| index)
+    }
+
+    // Cut a shelf of `shelf_height` from the top of the first empty shelf
+    // that fits `height`, or use all of it if it's shorter.
+    fn split_empty_shelf(&mut self, height: i32, shelf_height: i32) -> Option<usize> {
+        let index = self.shelves.iter().position(|shelf| shelf.is_empty() && shelf.height >= height)?;
+        let shelf = &mut self.shelves[index];
+        if shelf.height > shelf_height {
+            let rest = Shelf::new(shelf.y + shelf_height, shelf.height - shelf_height, self.width);
+            shelf.height = shelf_height;
+            self.shelves.insert(index + 1, rest);
+        }
+        Some(index)
+    }
+
+    /// Free a rectangle returned by `allocate`.
+    pub fn deallocate(&mut self, rect: Rect) {
+        let index = self.shelves.iter()
+                                .position(|shelf| shelf.y == rect.y)
+                                .expect("deallocated rect isn't on a shelf");
+        {
+            let spans = &mut self.shelves[index].spans;
+            let i = spans.iter()
+                         .position(|span| span.allocated && span.x == rect.x && span.width == rect.width)
+                         .expect("deallocated rect isn't allocated");
+            spans[i].allocated = false;
+            if i + 1 < spans.len() && !spans[i + 1].allocated {
+                spans[i].width += spans[i + 1].width;
+                spans.remove(i + 1);
+            }
+            if i > 0 && !spans[i - 1].allocated {
+                spans[i - 1].width += spans[i].width;
+                spans.remove(i);
+            }
+        }
+        self.allocated_area -= rect.width as i64 * rect.height as i64;
+
+        if self.shelves[index].is_empty() {
+            self.merge_empty_shelves(index);
+        }
+    }
+
+    // Merge the empty shelf at `index` with empty shelves above and below.
+    fn merge_empty_shelves(&mut self, index: usize) {
+        if index + 1 < self.shelves.len() && self.shelves[index + 1].is_empty() {
+            let below = self.shelves.remove(index + 1);
+            self.shelves[index].height += below.height;
+        }
+        if index > 0 && self.shelves[index - 1].is_empty() {
+            let shelf = self.shelves.remove(index);
+            self.shelves[index - 1].height += shelf.height;
+        }
+    }
+
+    pub fn allocated_area(&self) -> i64 {
+        self.allocated_area
+    }
+
+    pub fn free_area(&self) -> i64 {
+        self.width as i64 * self.height as i64 - self.allocated_area
+    }
+
+    /// The area of the largest free rectangle that can be allocated.
+    pub fn largest_free_area(&self) -> i64 {
+        self.shelves.iter().map(|shelf| {
+            let width = shelf.spans
+                             .iter()
+                             .filter(|span| !span.allocated)
+                             .map(|span| span.width)
+                             .max()
+                             .unwrap_or(0);
+            width as i64 * shelf.height as i64
+        }).max().unwrap_or(0)
+    }
+}
+
+#[derive(Copy, Clone, Hash, Eq, PartialEq)]
+pub struct AtlasId(usize);
+
+/// Where an image was placed. `uv_rect` is (u0, v0, u1, v1) within the
+/// page's texture, ready to be put in instance data.
+#[derive(Debug, Copy, Clone)]
+pub struct AtlasEntry {
+    pub page: usize,
+    pub rect: Rect,
+    pub uv_rect: [f32; 4],
+}
+
+/// Space used and lost across all pages of an atlas. Fragmentation is the
+/// fraction of free space outside the largest free rectangle of each
+/// page, so it rises as free space is split into small pieces.
+#[derive(Debug, Copy, Clone)]
+pub struct AtlasStats {
+    pub pages: usize,
+    pub entries: usize,
+    pub allocated_area: i64,
+    pub free_area: i64,
+    pub largest_free_area: i64,
+    pub fragmentation: f32,
+}
+
+struct AtlasPage {
+    texture: Texture,
+    allocator: ShelfAllocator,
+}
+
+/// Packs RGBA8 images into square pages, so that images on the same page
+/// can be drawn with a single texture binding. Pages are added as needed.
+pub struct TextureAtlas {
+    page_size: u32,
+    filter: TextureFilter,
+    pages: Vec<AtlasPage>,
+    entries: Vec<Option<AtlasEntry>>,
+    free_ids: Vec<usize>,
+}
+
+impl TextureAtlas {
+    /// Filtering is nearest or linear, as mipmaps would blend entries.
+    pub fn new(page_size: u32, filter: TextureFilter) -> Result<TextureAtlas, String> {
+        if filter == TextureFilter::Trilinear {
+            return Err("texture atlases don't support trilinear filtering".to_owned());
+        }
+        let max_size = gl::get_integer_v(gl::MAX_TEXTURE_SIZE) as u32;
+        if page_size == 0 || page_size > max_size {
+            return Err(format!("atlas page size {} is outside the supported sizes (1 to {})",
+                               page_size,
+                               max_size));
+        }
+
+        Ok(TextureAtlas {
+            page_size,
+            filter,
+            pages: Vec::new(),
+            entries: Vec::new(),
+            free_ids: Vec::new(),
+        })
+    }
+
+    /// Copy top-down RGBA8 pixels into the atlas.
+    pub fn add(&mut self, width: u32, height: u32, pixels: &[u8]) -> Result<AtlasId, String> {
+        if pixels.len() != width as usize * height as usize * 4 {
+            return Err(format!("{}x{} RGBA8 image needs {} bytes, but has {}",
+                               width,
+                               height,
+                               width as usize * height as usize * 4,
+                               pixels.len()));
+        }
+        let padded_width = width as i32 + 2 * GUTTER;
+        let padded_height = height as i32 + 2 * GUTTER;
+        if width == 0 || height == 0 || padded_width > self.page_size as i32 ||
+           padded_height > self.page_size as i32 {
+            return Err(format!("{}x{} image doesn't fit in a {}x{} atlas page",
+                               width,
+                               height,
+                               self.page_size,
+                               self.page_size));
+        }
+
+        let mut placement = None;
+        for (page, atlas_page) in self.pages.iter_mut().enumerate() {
+            if let Ok(rect) = atlas_page.allocator.allocate(padded_width, padded_height) {
+                placement = Some((page, rect));
+                break;
+            }
+        }
+        let (page, padded_rect) = match placement {
+            Some(placement) => placement,
+            None => {
+                let mut atlas_page = self.create_page()?;
+                let rect = atlas_page.allocator.allocate(padded_width, padded_height)?;
+                self.pages.push(atlas_page);
+                (self.pages.len() - 1, rect)
+            }
+        };
+
+        gl::bind_texture(gl::TEXTURE_2D, self.pages[page].texture.id);
+        gl::tex_sub_image_2d(gl::TEXTURE_2D,
+                             0,
+                             padded_rect.x,
+                             padded_rect.y,
+                             padded_width,
+                             padded_height,
+                             gl::RGBA,
+                             gl::UNSIGNED_BYTE,
+                             &add_gutter(width as usize, height as usize, pixels));
+        gl::bind_texture(gl::TEXTURE_2D, 0);
+
+        let rect = Rect::new(padded_rect.x + GUTTER, padded_rect.y + GUTTER, width as i32, height as i32);
+        let size = self.page_size as f32;
+        let entry = AtlasEntry {
+            page,
+            rect,
+            uv_rect: [
+                rect.x as f32 / size,
+                rect.y as f32 / size,
+                (rect.x + rect.width) as f32 / size,
+                (rect.y + rect.height) as f32 / size,
+            ],
+        };
+
+        let id = match self.free_ids.pop() {
+            Some(id) => {
+                self.entries[id] = Some(entry);
+                id
+            }
+            None => {
+                self.entries.push(Some(entry));
+                self.entries.len() - 1
+            }
+        };
+        Ok(AtlasId(id))
+    }
+
+    fn create_page(&self) -> Result<AtlasPage, String> {
+        let options = TextureOptions {
+            filter: self.filter,
+            wrap: TextureWrap::Clamp,
+        };
+        Ok(AtlasPage {
+            texture: Texture::new(self.page_size, self.page_size, None, options)?,
+            allocator: ShelfAllocator::new(self.page_size as i32, self.page_size as i32),
+        })
+    }
+
+    /// Free an image's space for reuse. Pages are kept once created.
+    pub fn remove(&mut self, id: AtlasId) {
+        let entry = self.entries[id.0].take().expect("removed atlas entry twice");
+        let padded_rect = Rect::new(entry.rect.x - GUTTER,
+                                    entry.rect.y - GUTTER,
+                                    entry.rect.width + 2 * GUTTER,
+                                    entry.rect.height + 2 * GUTTER);
+        self.pages[entry.page].allocator.deallocate(padded_rect);
+        self.free_ids.push(id.0);
+    }
+
+    pub fn entry(&self, id: AtlasId) -> &AtlasEntry {
+        self.entries[id.0].as_ref().expect("atlas entry was removed")
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// The texture of a page, to bind to a sampler with `bind_texture`.
+    pub fn page_binding(&self, page: usize) -> TextureBinding {
+        TextureBinding {
+            target: gl::TEXTURE_2D,
+            id: self.pages[page].texture.id,
+            kind: SamplerKind::Float,
+        }
+    }
+
+    pub fn stats(&self) -> AtlasStats {
+        let free_area = self.pages.iter().map(|page| page.allocator.free_area()).sum();
+        let unfragmented_area: i64 = self.pages.iter().map(|page| page.allocator.largest_free_area()).sum();
+        let fragmentation = if free_area > 0 {
+            1.0 - unfragmented_area as f32 / free_area as f32
+        } else {
+            0.0
+        };
+
+        AtlasStats {
+            pages: self.pages.len(),
+            entries: self.entries.iter().filter(|entry| entry.is_some()).count(),
+            allocated_area: self.pages.iter().map(|page| page.allocator.allocated_area()).sum(),
+            free_area,
+            largest_free_area: self.pages.iter().map(|page| page.allocator.largest_free_area()).max().unwrap_or(0),
+            fragmentation,
+        }
+    }
+}
+
+// Surround an image with a copy of its edge pixels.
+fn add_gutter(width: usize, height: usize, pixels: &[u8]) -> Vec<u8> {
+    let gutter = GUTTER as usize;
+    let padded_width = width + 2 * gutter;
+    let mut padded = Vec::with_capacity(padded_width * (height + 2 * gutter) * 4);
+    for y in 0..height + 2 * gutter {
+        let src_y = y.saturating_sub(gutter).min(height - 1);
+        let row = &pixels[src_y * width * 4..(src_y + 1) * width * 4];
+        for x in 0..padded_width {
+            let src_x = x.saturating_sub(gutter).min(width - 1);
+            padded.extend_from_slice(&row[src_x * 4..src_x * 4 + 4]);
+        }
+    }
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShelfAllocator;
+    use types::Rect;
+
+    fn overlaps(a: &Rect, b: &Rect) -> bool {
+        let overlap = a.intersection(b);
+        overlap.width > 0 && overlap.height > 0
+    }
+
+    fn random(state: &mut u32, max: u32) -> i32 {
+        *state ^= *state << 13;
+        *state ^= *state >> 17;
+        *state ^= *state << 5;
+        (*state % max) as i32
+    }
+
+    #[test]
+    fn allocations_dont_overlap() {
+        let mut allocator = ShelfAllocator::new(256, 256);
+        let mut rects: Vec<Rect> = Vec::new();
+        let mut rng = 0x2545f491;
+        for i in 0..2000 {
+            // Free some of the rectangles as we go, so that space is reused.
+            if i % 3 == 0 && !rects.is_empty() {
+                let index = random(&mut rng, rects.len() as u32) as usize;
+                allocator.deallocate(rects.swap_remove(index));
+            }
+            let width = 1 + random(&mut rng, 40);
+            let height = 1 + random(&mut rng, 40);
+            if let Ok(rect) = allocator.allocate(width, height) {
+                assert_eq!((rect.width, rect.height), (width, height));
+                assert!(rect.x >= 0 && rect.y >= 0 && rect.x + width <= 256 && rect.y + height <= 256);
+                for other in &rects {
+                    assert!(!overlaps(&rect, other), "{:?} overlaps {:?}", rect, other);
+                }
+                rects.push(rect);
+            }
+        }
+        let area: i64 = rects.iter().map(|r| r.width as i64 * r.height as i64).sum();
+        assert_eq!(allocator.allocated_area(), area);
+    }
+
+    #[test]
+    fn freed_space_is_reused() {
+        let mut allocator = ShelfAllocator::new(64, 64);
+        let mut rects = Vec::new();
+        while let Ok(rect) = allocator.allocate(16, 16) {
+            rects.push(rect);
+        }
+        assert_eq!(rects.len(), 16);
+        assert_eq!(allocator.free_area(), 0);
+
+        let freed = rects[5];
+        allocator.deallocate(freed);
+        assert_eq!(allocator.allocate(16, 16), Ok(freed));
+        assert!(allocator.allocate(16, 16).is_err());
+    }
+
+    #[test]
+    fn adjacent_spans_coalesce() {
+        let mut allocator = ShelfAllocator::new(64, 64);
+        let rects: Vec<Rect> = (0..4).map(|_| allocator.allocate(16, 16).unwrap()).collect();
+        assert!(rects.iter().all(|r| r.y == rects[0].y));
+        // Fill the rest of the page so that only the freed spans are free.
+        while allocator.allocate(16, 16).is_ok() {}
+
+        // Free the middle two spans out of order, then the outer ones.
+        allocator.deallocate(rects[2]);
+        allocator.deallocate(rects[1]);
+        assert!(allocator.allocate(48, 16).is_err());
+        assert_eq!(allocator.allocate(32, 16), Ok(Rect::new(16, rects[0].y, 32, 16)));
+        allocator.deallocate(Rect::new(16, rects[0].y, 32, 16));
+        allocator.deallocate(rects[0]);
+        allocator.deallocate(rects[3]);
+        assert_eq!(allocator.allocate(64, 16), Ok(Rect::new(0, rects[0].y, 64, 16)));
+    }
+
+    #[test]
+    fn empty_shelves_merge() {
+        let mut allocator = ShelfAllocator::new(64, 64);
+        let rects: Vec<Rect> = (0..4).map(|_| allocator.allocate(64, 16).unwrap()).collect();
+        for rect in rects {
+            allocator.deallocate(rect);
+        }
+        assert_eq!(allocator.largest_free_area(), 64 * 64);
+        assert_eq!(allocator.allocate(64, 64), Ok(Rect::new(0, 0, 64, 64)));
+    }
+
+    #[test]
+    fn oversized_requests_fail() {
+        let mut allocator = ShelfAllocator::new(64, 32);
+        assert!(allocator.allocate(65, 1).is_err());
+        assert!(allocator.allocate(1, 33).is_err());
+        assert!(allocator.allocate(0, 1).is_err());
+        assert!(allocator.allocate(1, -1).is_err());
+        assert_eq!(allocator.allocated_area(), 0);
+
+        assert_eq!(allocator.allocate(64, 32), Ok(Rect::new(0, 0, 64, 32)));
+        assert!(allocator.allocate(1, 1).is_err());
+    }
+}
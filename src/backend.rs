@@ -0,0 +1,409 @@
+use gleam::gl;
+use glutin;
+use glutin::WindowProxy;
+use shader_cache;
+use upload;
+
+// Minimal EGL bindings, enough to create a surfaceless (or pbuffer backed)
+// desktop GL context. This is what Mesa provides on machines without an
+// X server, including the llvmpipe software rasterizer. libEGL is loaded
+// when a headless context is created, so the windowed backend runs on
+// machines that don't have it.
+#[cfg(target_os = "linux")]
+#[allow(non_camel_case_types, non_snake_case)]
+mod egl {
+    use std::ffi::CString;
+    use std::mem;
+    use std::os::raw::{c_char, c_int, c_void};
+
+    pub type EGLint = i32;
+    pub type EGLenum = u32;
+    pub type EGLBoolean = u32;
+    pub type EGLDisplay = *mut c_void;
+    pub type EGLConfig = *mut c_void;
+    pub type EGLContext = *mut c_void;
+    pub type EGLSurface = *mut c_void;
+
+    pub const EGL_NONE: EGLint = 0x3038;
+    pub const EGL_RED_SIZE: EGLint = 0x3024;
+    pub const EGL_GREEN_SIZE: EGLint = 0x3023;
+    pub const EGL_BLUE_SIZE: EGLint = 0x3022;
+    pub const EGL_ALPHA_SIZE: EGLint = 0x3021;
+    pub const EGL_SURFACE_TYPE: EGLint = 0x3033;
+    pub const EGL_PBUFFER_BIT: EGLint = 0x0001;
+    pub const EGL_RENDERABLE_TYPE: EGLint = 0x3040;
+    pub const EGL_OPENGL_BIT: EGLint = 0x0008;
+    pub const EGL_WIDTH: EGLint = 0x3057;
+    pub const EGL_HEIGHT: EGLint = 0x3056;
+    pub const EGL_OPENGL_API: EGLenum = 0x30A2;
+    pub const EGL_CONTEXT_MAJOR_VERSION: EGLint = 0x3098;
+    pub const EGL_CONTEXT_MINOR_VERSION: EGLint = 0x30FB;
+    pub const EGL_CONTEXT_OPENGL_PROFILE_MASK: EGLint = 0x30FD;
+    pub const EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT: EGLint = 0x0001;
+    pub const EGL_PLATFORM_SURFACELESS_MESA: EGLenum = 0x31DD;
+
+    pub type GetPlatformDisplayFn = extern "C" fn(EGLenum, *mut c_void, *const EGLint) -> EGLDisplay;
+
+    const RTLD_NOW: c_int = 2;
+    const RTLD_LOCAL: c_int = 0;
+
+    #[link(name = "dl")]
+    extern "C" {
+        fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+        fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    }
+
+    type GetDisplayFn = unsafe extern "C" fn(*mut c_void) -> EGLDisplay;
+    type InitializeFn = unsafe extern "C" fn(EGLDisplay, *mut EGLint, *mut EGLint) -> EGLBoolean;
+    type TerminateFn = unsafe extern "C" fn(EGLDisplay) -> EGLBoolean;
+    type BindAPIFn = unsafe extern "C" fn(EGLenum) -> EGLBoolean;
+    type ChooseConfigFn = unsafe extern "C" fn(EGLDisplay, *const EGLint, *mut EGLConfig, EGLint, *mut EGLint) -> EGLBoolean;
+    type CreateContextFn = unsafe extern "C" fn(EGLDisplay, EGLConfig, EGLContext, *const EGLint) -> EGLContext;
+    type DestroyContextFn = unsafe extern "C" fn(EGLDisplay, EGLContext) -> EGLBoolean;
+    type CreatePbufferSurfaceFn = unsafe extern "C" fn(EGLDisplay, EGLConfig, *const EGLint) -> EGLSurface;
+    type DestroySurfaceFn = unsafe extern "C" fn(EGLDisplay, EGLSurface) -> EGLBoolean;
+    type MakeCurrentFn = unsafe extern "C" fn(EGLDisplay, EGLSurface, EGLSurface, EGLContext) -> EGLBoolean;
+    type GetProcAddressFn = unsafe extern "C" fn(*const c_char) -> *const c_void;
+
+    /// The EGL entry points, looked up in libEGL at runtime. The library
+    /// stays loaded for the rest of the process.
+    pub struct Egl {
+        pub eglGetDisplay: GetDisplayFn,
+        pub eglInitialize: InitializeFn,
+        pub eglTerminate: TerminateFn,
+        pub eglBindAPI: BindAPIFn,
+        pub eglChooseConfig: ChooseConfigFn,
+        pub eglCreateContext: CreateContextFn,
+        pub eglDestroyContext: DestroyContextFn,
+        pub eglCreatePbufferSurface: CreatePbufferSurfaceFn,
+        pub eglDestroySurface: DestroySurfaceFn,
+        pub eglMakeCurrent: MakeCurrentFn,
+        pub eglGetProcAddress: GetProcAddressFn,
+    }
+
+    impl Egl {
+        pub fn load() -> Result<Egl, String> {
+            let library = ["libEGL.so.1", "libEGL.so"].iter().map(|name| {
+                let name = CString::new(*name).unwrap();
+                unsafe { dlopen(name.as_ptr(), RTLD_NOW | RTLD_LOCAL) }
+            }).find(|library| !library.is_null());
+            let library = match library {
+                Some(library) => library,
+                None => return Err("unable to load libEGL".to_owned()),
+            };
+
+            let symbol = |name: &str| {
+                let c_name = CString::new(name).unwrap();
+                let symbol = unsafe { dlsym(library, c_name.as_ptr()) };
+                if symbol.is_null() {
+                    Err(format!("libEGL is missing {}", name))
+                } else {
+                    Ok(symbol)
+                }
+            };
+
+            unsafe {
+                Ok(Egl {
+                    eglGetDisplay: mem::transmute::<*mut c_void, GetDisplayFn>(symbol("eglGetDisplay")?),
+                    eglInitialize: mem::transmute::<*mut c_void, InitializeFn>(symbol("eglInitialize")?),
+                    eglTerminate: mem::transmute::<*mut c_void, TerminateFn>(symbol("eglTerminate")?),
+                    eglBindAPI: mem::transmute::<*mut c_void, BindAPIFn>(symbol("eglBindAPI")?),
+                    eglChooseConfig: mem::transmute::<*mut c_void, ChooseConfigFn>(symbol("eglChooseConfig")?),
+                    eglCreateContext: mem::transmute::<*mut c_void, CreateContextFn>(symbol("eglCreateContext")?),
+                    eglDestroyContext: mem::transmute::<*mut c_void, DestroyContextFn>(symbol("eglDestroyContext")?),
+                    eglCreatePbufferSurface: mem::transmute::<*mut c_void, CreatePbufferSurfaceFn>(symbol("eglCreatePbufferSurface")?),
+                    eglDestroySurface: mem::transmute::<*mut c_void, DestroySurfaceFn>(symbol("eglDestroySurface")?),
+                    eglMakeCurrent: mem::transmute::<*mut c_void, MakeCurrentFn>(symbol("eglMakeCurrent")?),
+                    eglGetProcAddress: mem::transmute::<*mut c_void, GetProcAddressFn>(symbol("eglGetProcAddress")?),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+struct HeadlessContext {
+    egl: egl::Egl,
+    display: egl::EGLDisplay,
+    context: egl::EGLContext,
+    surface: egl::EGLSurface,
+}
+
+#[cfg(target_os = "linux")]
+impl HeadlessContext {
+    fn new(width: u32, height: u32) -> Result<HeadlessContext, String> {
+        use std::ffi::CString;
+        use std::ptr;
+
+        let egl = egl::Egl::load()?;
+        unsafe {
+            // Prefer the Mesa surfaceless platform, which needs no display
+            // server at all. Fall back to the default display otherwise.
+            let name = CString::new("eglGetPlatformDisplayEXT").unwrap();
+            let get_platform_display = (egl.eglGetProcAddress)(name.as_ptr());
+            let mut display = ptr::null_mut();
+            if !get_platform_display.is_null() {
+                let get_platform_display: egl::GetPlatformDisplayFn =
+                    ::std::mem::transmute::<*const _, egl::GetPlatformDisplayFn>(get_platform_display);
+                display = get_platform_display(egl::EGL_PLATFORM_SURFACELESS_MESA,
+                                               ptr::null_mut(),
+                                               ptr::null());
+            }
+            if display.is_null() {
+                display = (egl.eglGetDisplay)(ptr::null_mut());
+            }
+            if display.is_null() {
+                return Err("no EGL display available".to_owned());
+            }
+
+            let (mut major, mut minor) = (0, 0);
+            if (egl.eglInitialize)(display, &mut major, &mut minor) == 0 {
+                return Err("eglInitialize failed".to_owned());
+            }
+            if (egl.eglBindAPI)(egl::EGL_OPENGL_API) == 0 {
+                return Err("EGL implementation does not support desktop GL".to_owned());
+            }
+
+            let config_attribs = [
+                egl::EGL_SURFACE_TYPE, egl::EGL_PBUFFER_BIT,
+                egl::EGL_RENDERABLE_TYPE, egl::EGL_OPENGL_BIT,
+                egl::EGL_RED_SIZE, 8,
+                egl::EGL_GREEN_SIZE, 8,
+                egl::EGL_BLUE_SIZE, 8,
+                egl::EGL_ALPHA_SIZE, 8,
+                egl::EGL_NONE,
+            ];
+            let mut config = ptr::null_mut();
+            let mut config_count = 0;
+            if (egl.eglChooseConfig)(display,
+                                      config_attribs.as_ptr(),
+                                      &mut config,
+                                      1,
+                                      &mut config_count) == 0 || config_count == 0 {
+                (egl.eglTerminate)(display);
+                return Err("no suitable EGL config".to_owned());
+            }
+
+            let context_attribs = [
+                egl::EGL_CONTEXT_MAJOR_VERSION, 3,
+                egl::EGL_CONTEXT_MINOR_VERSION, 3,
+                egl::EGL_CONTEXT_OPENGL_PROFILE_MASK, egl::EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT,
+                egl::EGL_NONE,
+            ];
+            let context = (egl.eglCreateContext)(display,
+                                                  config,
+                                                  ptr::null_mut(),
+                                                  context_attribs.as_ptr());
+            if context.is_null() {
+                (egl.eglTerminate)(display);
+                return Err("eglCreateContext failed".to_owned());
+            }
+
+            // Rendering always goes to our own framebuffer object, so a
+            // surface is only needed if the driver lacks surfaceless support.
+            let mut surface = ptr::null_mut();
+            if (egl.eglMakeCurrent)(display, surface, surface, context) == 0 {
+                let pbuffer_attribs = [
+                    egl::EGL_WIDTH, width as egl::EGLint,
+                    egl::EGL_HEIGHT, height as egl::EGLint,
+                    egl::EGL_NONE,
+                ];
+                surface = (egl.eglCreatePbufferSurface)(display, config, pbuffer_attribs.as_ptr());
+                if surface.is_null() ||
+                   (egl.eglMakeCurrent)(display, surface, surface, context) == 0 {
+                    (egl.eglDestroyContext)(display, context);
+                    (egl.eglTerminate)(display);
+                    return Err("eglMakeCurrent failed".to_owned());
+                }
+            }
+
+            Ok(HeadlessContext {
+                egl,
+                display,
+                context,
+                surface,
+            })
+        }
+    }
+
+    fn get_proc_address(&self, name: &str) -> *const () {
+        let name = ::std::ffi::CString::new(name).unwrap();
+        unsafe { (self.egl.eglGetProcAddress)(name.as_ptr()) as *const () }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for HeadlessContext {
+    fn drop(&mut self) {
+        unsafe {
+            (self.egl.eglMakeCurrent)(self.display,
+                                       ::std::ptr::null_mut(),
+                                       ::std::ptr::null_mut(),
+                                       ::std::ptr::null_mut());
+            if !self.surface.is_null() {
+                (self.egl.eglDestroySurface)(self.display, self.surface);
+            }
+            (self.egl.eglDestroyContext)(self.display, self.context);
+            (self.egl.eglTerminate)(self.display);
+        }
+    }
+}
+
+// Other platforms use whatever headless support glutin has (OSMesa).
+#[cfg(not(target_os = "linux"))]
+struct HeadlessContext {
+    context: glutin::HeadlessContext,
+}
+
+#[cfg(not(target_os = "linux"))]
+impl HeadlessContext {
+    fn new(width: u32, height: u32) -> Result<HeadlessContext, String> {
+        let context = glutin::HeadlessRendererBuilder::new(width, height)
+                          .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (3, 3)))
+                          .with_gl_profile(glutin::GlProfile::Core)
+                          .build()
+                          .map_err(|e| format!("{:?}", e))?;
+        unsafe { context.make_current() }.map_err(|e| format!("{:?}", e))?;
+        Ok(HeadlessContext {
+            context: context,
+        })
+    }
+
+    fn get_proc_address(&self, name: &str) -> *const () {
+        self.context.get_proc_address(name)
+    }
+}
+
+/// Offscreen color and depth-stencil buffers that stand in for the
+/// default framebuffer when there is no window to present to.
+struct OffscreenTarget {
+    fbo: gl::GLuint,
+    color_rb: gl::GLuint,
+    depth_stencil_rb: gl::GLuint,
+    width: u32,
+    height: u32,
+}
+
+impl OffscreenTarget {
+    fn new(width: u32, height: u32) -> OffscreenTarget {
+        let fbo = gl::gen_framebuffers(1)[0];
+        let renderbuffers = gl::gen_renderbuffers(2);
+        let color_rb = renderbuffers[0];
+        let depth_stencil_rb = renderbuffers[1];
+
+        gl::bind_renderbuffer(gl::RENDERBUFFER, color_rb);
+        gl::renderbuffer_storage(gl::RENDERBUFFER,
+                                 gl::RGBA8,
+                                 width as gl::GLsizei,
+                                 height as gl::GLsizei);
+        gl::bind_renderbuffer(gl::RENDERBUFFER, depth_stencil_rb);
+        gl::renderbuffer_storage(gl::RENDERBUFFER,
+                                 gl::DEPTH24_STENCIL8,
+                                 width as gl::GLsizei,
+                                 height as gl::GLsizei);
+
+        gl::bind_framebuffer(gl::FRAMEBUFFER, fbo);
+        gl::framebuffer_renderbuffer(gl::FRAMEBUFFER,
+                                     gl::COLOR_ATTACHMENT0,
+                                     gl::RENDERBUFFER,
+                                     color_rb);
+        gl::framebuffer_renderbuffer(gl::FRAMEBUFFER,
+                                     gl::DEPTH_STENCIL_ATTACHMENT,
+                                     gl::RENDERBUFFER,
+                                     depth_stencil_rb);
+        gl::viewport(0, 0, width as gl::GLint, height as gl::GLint);
+
+        OffscreenTarget {
+            fbo,
+            color_rb,
+            depth_stencil_rb,
+            width,
+            height,
+        }
+    }
+}
+
+impl Drop for OffscreenTarget {
+    fn drop(&mut self) {
+        gl::delete_framebuffers(&[self.fbo]);
+        gl::delete_renderbuffers(&[self.color_rb, self.depth_stencil_rb]);
+    }
+}
+
+enum Surface {
+    Window(Box<glutin::Window>),
+    // The target must be dropped before the context that owns it.
+    Headless {
+        target: OffscreenTarget,
+        _context: HeadlessContext,
+    },
+}
+
+/// Owns the GL context that apps render with - either a visible
+/// window or a headless context drawing into an offscreen target.
+pub struct Backend {
+    surface: Surface,
+}
+
+impl Backend {
+    pub fn new_window(title: &str, width: u32, height: u32) -> Result<Backend, String> {
+        let window = glutin::WindowBuilder::new().with_dimensions(width, height)
+                                                 .with_title(title)
+                                                 .with_depth_buffer(24)
+                                                 .with_stencil_buffer(8)
+                                                 .build()
+                                                 .map_err(|e| format!("{:?}", e))?;
+        unsafe { window.make_current() }.map_err(|e| format!("{:?}", e))?;
+        gl::load_with(|s| window.get_proc_address(s) as *const _);
+        shader_cache::load_with(|s| window.get_proc_address(s) as *const _);
+        upload::load_with(|s| window.get_proc_address(s) as *const _);
+        Ok(Backend {
+            surface: Surface::Window(Box::new(window)),
+        })
+    }
+
+    pub fn new_headless(width: u32, height: u32) -> Result<Backend, String> {
+        let context = HeadlessContext::new(width, height)?;
+        gl::load_with(|s| context.get_proc_address(s) as *const _);
+        shader_cache::load_with(|s| context.get_proc_address(s) as *const _);
+        upload::load_with(|s| context.get_proc_address(s) as *const _);
+        let target = OffscreenTarget::new(width, height);
+        Ok(Backend {
+            surface: Surface::Headless {
+                target,
+                _context: context,
+            },
+        })
+    }
+
+    pub fn window(&self) -> Option<&glutin::Window> {
+        match self.surface {
+            Surface::Window(ref window) => Some(window),
+            Surface::Headless { .. } => None,
+        }
+    }
+
+    pub fn create_window_proxy(&self) -> Option<WindowProxy> {
+        self.window().map(|window| window.create_window_proxy())
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        match self.surface {
+            Surface::Window(ref window) => window.get_inner_size().unwrap(),
+            Surface::Headless { ref target, .. } => (target.width, target.height),
+        }
+    }
+
+    pub fn swap_buffers(&self) {
+        match self.surface {
+            Surface::Window(ref window) => {
+                let _ = window.swap_buffers();
+            }
+            Surface::Headless { .. } => {
+                // Nothing is presented, but wait for the GPU so that
+                // frame times are comparable with a real swap.
+                gl::finish();
+            }
+        }
+    }
+}
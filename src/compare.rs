@@ -0,0 +1,140 @@
+use options::Options;
+use report::{AppRecord, Report};
+use stats::{self, Summary};
+use std::path::Path;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Metric {
+    Total,
+    Cpu,
+    Swap,
+    Gpu,
+}
+
+impl Metric {
+    pub fn parse(name: &str) -> Option<Metric> {
+        match name {
+            "total" => Some(Metric::Total),
+            "cpu" => Some(Metric::Cpu),
+            "swap" => Some(Metric::Swap),
+            "gpu" => Some(Metric::Gpu),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            Metric::Total => "total",
+            Metric::Cpu => "cpu",
+            Metric::Swap => "swap",
+            Metric::Gpu => "gpu",
+        }
+    }
+
+    /// Per-frame samples for this metric, in milliseconds.
+    fn samples(&self, record: &AppRecord) -> Vec<f64> {
+        let stats = &record.stats;
+        let ns: Vec<u64> = match *self {
+            Metric::Total => stats.frames.iter().map(|t| t.total_ns).collect(),
+            Metric::Cpu => stats.frames.iter().map(|t| t.cpu_ns).collect(),
+            Metric::Swap => stats.frames.iter().map(|t| t.swap_ns).collect(),
+            Metric::Gpu => stats.gpu_frames.clone(),
+        };
+        ns.into_iter().map(|ns| ns as f64 / 1000000.0).collect()
+    }
+}
+
+fn load(path: &str) -> Result<Report, i32> {
+    Report::load(Path::new(path)).map_err(|e| {
+        eprintln!("{}", e);
+        2
+    })
+}
+
+/// Compare a new report against a baseline, app by app. An app regresses
+/// when its median frame time grew by more than the threshold and the
+/// difference is statistically significant. Apps missing from the new
+/// report or without samples fail. Returns the process exit code: 0 if
+/// nothing regressed or failed, 1 on regressions or failures and 2 on
+/// errors.
+pub fn run_compare(options: &Options) -> i32 {
+    if options.app_args.len() != 2 {
+        eprintln!("compare expects a baseline report and a new report");
+        return 2;
+    }
+
+    let (baseline, current) = match (load(&options.app_args[0]), load(&options.app_args[1])) {
+        (Ok(baseline), Ok(current)) => (baseline, current),
+        (Err(code), _) | (_, Err(code)) => return code,
+    };
+
+    println!("metric={} threshold={}% alpha={}", options.metric.name(), options.threshold, options.alpha);
+    println!("{:<32} {:>12} {:>12} {:>9} {:>9}  result",
+             "app", "baseline", "new", "delta", "p");
+
+    let mut regressions = 0;
+    let mut failures = 0;
+    for new_record in &current.records {
+        let base_record = match baseline.records.iter().find(|r| r.name == new_record.name) {
+            Some(record) => record,
+            None => {
+                println!("{:<32} not in baseline", new_record.name);
+                continue;
+            }
+        };
+
+        let base_samples = options.metric.samples(base_record);
+        let new_samples = options.metric.samples(new_record);
+        if base_samples.is_empty() || new_samples.is_empty() {
+            println!("{:<32} no samples  FAILED", new_record.name);
+            failures += 1;
+            continue;
+        }
+
+        let base = Summary::from_samples(&base_samples);
+        let new = Summary::from_samples(&new_samples);
+        let delta = if base.median > 0.0 {
+            100.0 * (new.median - base.median) / base.median
+        } else {
+            0.0
+        };
+        let p = stats::mann_whitney_p(&base_samples, &new_samples);
+
+        let significant = p < options.alpha;
+        let result = if significant && delta > options.threshold {
+            regressions += 1;
+            "REGRESSED"
+        } else if significant && delta < -options.threshold {
+            "improved"
+        } else {
+            "ok"
+        };
+
+        println!("{:<32} {:>10.3}ms {:>10.3}ms {:>+8.2}% {:>9.4}  {}",
+                 new_record.name,
+                 base.median,
+                 new.median,
+                 delta,
+                 p,
+                 result);
+    }
+
+    for base_record in &baseline.records {
+        if !current.records.iter().any(|r| r.name == base_record.name) {
+            println!("{:<32} missing from new report  FAILED", base_record.name);
+            failures += 1;
+        }
+    }
+
+    if regressions > 0 {
+        println!("{} app(s) regressed", regressions);
+    }
+    if failures > 0 {
+        println!("{} app(s) failed", failures);
+    }
+    if regressions > 0 || failures > 0 {
+        1
+    } else {
+        0
+    }
+}
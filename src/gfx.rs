@@ -1,24 +1,27 @@
+use atlas::TextureAtlas;
 use gleam::gl;
 use glutin::WindowProxy;
-//use inotify::INotify;
-//use inotify::ffi::*;
+use gpu_profiler::{GpuFrameTimings, GpuProfiler};
+use instances::{self, InstanceBackend, InstanceBuffer, InstanceLayout};
+use pipeline::{PipelineState, StencilState};
+use preprocessor;
+use reflection::{self, ProgramReflection, SamplerKind, UniformValue};
+use render_target::{RenderTarget, RenderTargetFormat};
+use std::cmp;
 use std::collections::HashMap;
 use std::mem;
-use std::path::PathBuf;
-use std::sync::mpsc::{channel, Receiver};
-use std::thread;
-use types::ColorF;
+use std::slice;
+use shader_cache::{self, ShaderCache, ShaderStats};
+use shader_error::{ShaderError, ShaderErrorKind, ShaderStage};
+use std::path::{Path, PathBuf};
+use texture::{self, Texture, TextureFilter, TextureOptions};
+use types::{ColorF, Rect};
+use upload::{UploadMethod, UploadStats, Uploader};
 use util;
+use watcher::FileWatcher;
 
 const MAX_VERTEX_TEXTURE_WIDTH: usize = 1024;
 
-#[repr(u32)]
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub enum TextureSampler {
-    Float0,
-    Float1,
-}
-
 struct QuadVertex {
     _pos: [f32; 2],
 }
@@ -27,13 +30,108 @@ enum VertexAttribute {
     Position = 0,
 }
 
+// The sampler that texture instance backends bind their data to.
+const INSTANCE_SAMPLER: &str = "sInstances";
+
 #[derive(Copy, Clone, Hash, Eq, PartialEq)]
 pub struct ProgramId(usize);
 
+#[derive(Copy, Clone, Hash, Eq, PartialEq)]
+pub struct RenderTargetId(usize);
+
+#[derive(Copy, Clone, Hash, Eq, PartialEq)]
+pub struct TextureId(usize);
+
+/// A texture to bind to a sampler. `kind` must match the sampler type,
+/// so integer textures go to isampler2D and so on. Color textures are
+/// `SamplerKind::Float`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextureBinding {
+    pub target: gl::GLenum,
+    pub id: gl::GLuint,
+    pub kind: SamplerKind,
+}
+
+// A sampler declared by a program, and the texture unit it was given.
+struct SamplerSlot {
+    name: String,
+    ty: gl::GLenum,
+    kind: SamplerKind,
+    target: gl::GLenum,
+    unit: gl::GLuint,
+}
+
 struct Program {
     path: PathBuf,
+    defines: Vec<String>,
+    // Files that trigger a reload of this program when they change.
+    dependencies: Vec<PathBuf>,
     program: gl::GLuint,
     u_transform: gl::GLint,
+    u_first_instance: gl::GLint,
+    reflection: ProgramReflection,
+    samplers: Vec<SamplerSlot>,
+    // Values set through set_uniform, reapplied when the program reloads.
+    uniform_values: Vec<(String, UniformValue)>,
+    warned: Vec<String>,
+}
+
+impl Program {
+    /// Type check a value against the reflected uniform and assign it.
+    /// The program must be bound.
+    fn apply_uniform(&mut self, name: &str, value: UniformValue) {
+        let warning = match self.reflection.uniform(name) {
+            Some(uniform) if value.matches(uniform.ty) => {
+                value.apply(uniform.location);
+                return;
+            }
+            Some(uniform) => {
+                format!("{} is a {}, not a {}", name, reflection::type_name(uniform.ty), value.type_name())
+            }
+            None => format!("{} is not an active uniform", name),
+        };
+        self.warn_once(name, &warning);
+    }
+
+    /// Bind the texture for each of the program's samplers to its unit.
+    /// Returns false if any sampler has no texture, or one of the wrong type.
+    fn bind_samplers(&mut self, bindings: &[(String, TextureBinding)]) -> bool {
+        let mut problems = Vec::new();
+        for slot in &self.samplers {
+            match bindings.iter().find(|(name, _)| *name == slot.name) {
+                Some((_, binding)) if binding.kind == slot.kind && binding.target == slot.target => {
+                    gl::active_texture(gl::TEXTURE0 + slot.unit);
+                    gl::bind_texture(slot.target, binding.id);
+                }
+                Some((_, binding)) => {
+                    problems.push((slot.name.clone(),
+                                   format!("{} is a {}, but the texture bound to it needs a {}",
+                                           slot.name,
+                                           reflection::type_name(slot.ty),
+                                           reflection::sampler_type_name(binding.kind, binding.target))));
+                }
+                None => {
+                    problems.push((slot.name.clone(), format!("{} has no texture bound", slot.name)));
+                }
+            }
+        }
+        gl::active_texture(gl::TEXTURE0);
+
+        let complete = problems.is_empty();
+        for (name, problem) in problems {
+            self.warn_once(&name, &problem);
+        }
+        complete
+    }
+
+    // Only warn once per name, as uniforms are usually set and textures
+    // bound every frame.
+    fn warn_once(&mut self, name: &str, warning: &str) {
+        if !self.warned.iter().any(|w| w == name) {
+            eprintln!("Warning: {}: {}", self.path.display(), warning);
+            self.warned.push(name.to_owned());
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -44,14 +142,21 @@ pub enum VertexTextureFormat {
 
 pub struct VertexDataTexture {
     id: gl::GLuint,
-    sampler: TextureSampler,
+    format: VertexTextureFormat,
+    // Rows of MAX_VERTEX_TEXTURE_WIDTH texels allocated so far.
+    allocated_rows: usize,
+}
+
+impl Drop for VertexDataTexture {
+    fn drop(&mut self) {
+        gl::delete_textures(&[self.id]);
+    }
 }
 
 impl VertexDataTexture {
-    fn new(sampler: TextureSampler) -> VertexDataTexture {
+    pub fn new(format: VertexTextureFormat) -> VertexDataTexture {
         let id = gl::gen_textures(1)[0];
 
-        gl::active_texture(gl::TEXTURE0 + sampler as gl::GLuint);
         gl::bind_texture(gl::TEXTURE_2D, id);
 
         gl::tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as gl::GLint);
@@ -63,104 +168,136 @@ impl VertexDataTexture {
         gl::bind_texture(gl::TEXTURE_2D, 0);
 
         VertexDataTexture {
-            id: id,
-            sampler: sampler,
+            id,
+            format,
+            allocated_rows: 0,
+        }
+    }
+
+    pub fn binding(&self) -> TextureBinding {
+        let kind = match self.format {
+            VertexTextureFormat::I32 => SamplerKind::Int,
+            VertexTextureFormat::F32 => SamplerKind::Float,
+        };
+        TextureBinding {
+            target: gl::TEXTURE_2D,
+            id: self.id,
+            kind,
         }
     }
 
-    fn update_and_bind<T>(&self, data: &mut Vec<T>, format: VertexTextureFormat) {
+    /// Upload `data`, growing the texture storage when it doesn't fit.
+    /// Storage is kept between calls, and only the rows in use are written.
+    pub fn update<T>(&mut self, data: &[T], uploader: &mut Uploader) {
         if data.is_empty() {
             return;
         }
 
         let item_size = mem::size_of::<T>();
-        debug_assert!(item_size % 16 == 0);
+        debug_assert!(item_size.is_multiple_of(16));
         let vecs_per_item = item_size / 16;
 
         let items_per_row = MAX_VERTEX_TEXTURE_WIDTH / vecs_per_item;
+        let row_width = items_per_row * vecs_per_item;
+        let rows = data.len().div_ceil(items_per_row);
 
-        // Extend the data array to be a multiple of the row size.
-        // This ensures memory safety when the array is passed to
-        // OpenGL to upload to the GPU.
-        let mut dummy_items = 0;
-        while data.len() % items_per_row != 0 {
-            data.push(unsafe { mem::uninitialized() });
-            dummy_items += 1;
-        }
-
-        let width = items_per_row * vecs_per_item;
-        let height = data.len() / items_per_row;
+        let (internal_format, format, ty) = match self.format {
+            VertexTextureFormat::I32 => (gl::RGBA32I, gl::RGBA_INTEGER, gl::INT),
+            VertexTextureFormat::F32 => (gl::RGBA32F, gl::RGBA, gl::FLOAT),
+        };
 
-        gl::active_texture(gl::TEXTURE0 + self.sampler as gl::GLuint);
         gl::bind_texture(gl::TEXTURE_2D, self.id);
 
-        match format {
-            VertexTextureFormat::I32 => {
-                gl::tex_image_2d(gl::TEXTURE_2D,
-                                 0,
-                                 gl::RGBA32I as gl::GLint,
-                                 width as gl::GLint,
-                                 height as gl::GLint,
-                                 0,
-                                 gl::RGBA_INTEGER,
-                                 gl::INT,
-                                 Some(unsafe { mem::transmute(data.as_slice()) } ));
-            }
-            VertexTextureFormat::F32 => {
-                gl::tex_image_2d(gl::TEXTURE_2D,
-                                 0,
-                                 gl::RGBA32F as gl::GLint,
-                                 width as gl::GLint,
-                                 height as gl::GLint,
-                                 0,
-                                 gl::RGBA,
-                                 gl::FLOAT,
-                                 Some(unsafe { mem::transmute(data.as_slice()) } ));
-            }
+        if rows > self.allocated_rows {
+            self.allocated_rows = rows.next_power_of_two().max(self.allocated_rows * 2);
+            gl::tex_image_2d(gl::TEXTURE_2D,
+                             0,
+                             internal_format as gl::GLint,
+                             MAX_VERTEX_TEXTURE_WIDTH as gl::GLint,
+                             self.allocated_rows as gl::GLint,
+                             0,
+                             format,
+                             ty,
+                             None);
         }
 
-        // Remove dummy items
-        for _ in 0..dummy_items {
-            data.pop();
+        let bytes = unsafe {
+            slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data))
+        };
+
+        // The full rows go up in one call, and a partial last row in another,
+        // so nothing past the end of `data` is read.
+        let full_rows = data.len() / items_per_row;
+        let full_row_bytes = full_rows * items_per_row * item_size;
+        if full_rows > 0 {
+            uploader.upload_texture((0, 0), (row_width, full_rows), format, ty, &bytes[..full_row_bytes]);
+        }
+        let remaining = data.len() % items_per_row;
+        if remaining > 0 {
+            uploader.upload_texture((0, full_rows),
+                                    (remaining * vecs_per_item, 1),
+                                    format,
+                                    ty,
+                                    &bytes[full_row_bytes..]);
         }
+
+        gl::bind_texture(gl::TEXTURE_2D, 0);
     }
 }
 
 pub struct GfxContext {
     resource_path: PathBuf,
-    shared_path: PathBuf,
     quad_vao_id: gl::GLuint,
     quad_ibo: gl::GLuint,
+    quad_vbo: gl::GLuint,
     offset_x: f32,
     offset_y: f32,
     scale_x: f32,
     scale_y: f32,
     next_id: usize,
     programs: HashMap<ProgramId, Program>,
-    //watch_rx: Receiver<String>,
+    // Each permutation of a shader file and define set gets its own program.
+    program_cache: HashMap<(PathBuf, Vec<String>), ProgramId>,
+    error_program: gl::GLuint,
+    current_program: Option<ProgramId>,
+    // The state that draws should use, and what was last set in GL. None
+    // if the GL state isn't known.
+    pipeline_state: PipelineState,
+    applied_state: Option<PipelineState>,
+    render_targets: HashMap<RenderTargetId, RenderTarget>,
+    textures: HashMap<TextureId, Texture>,
+    // The framebuffer bound when the frame began, which the frame is
+    // presented from, and its size.
+    frame_fbo: gl::GLuint,
+    frame_width: u32,
+    frame_height: u32,
+    current_target: Option<RenderTargetId>,
+    // Nested clip rectangles of the current target, each already
+    // intersected with those below it. The top one is the scissor rect.
+    clip_stack: Vec<Rect>,
+    // Textures bound by sampler name, cleared at the start of each frame.
+    texture_bindings: Vec<(String, TextureBinding)>,
+    // Where draws find the bound instances.
+    instance_layout: InstanceLayout,
+    shader_cache: ShaderCache,
+    gpu_profiler: GpuProfiler,
+    uploader: Uploader,
+    watcher: FileWatcher,
 }
 
 impl GfxContext {
-    pub fn new(window_proxy: WindowProxy) -> GfxContext {
+    /// Shaders are reloaded when their files change. The window proxy,
+    /// if any, wakes up the event loop so that apps waiting on input redraw.
+    /// Texture and instance data is uploaded with `upload_method`.
+    pub fn new(window_proxy: Option<WindowProxy>, upload_method: UploadMethod) -> GfxContext {
         let res_path = PathBuf::from("res/");
 
-        let mut shared_path = res_path.clone();
-        shared_path.push("shared.glsl");
-
-/*
-        let (watch_tx, watch_rx) = channel();
-        let watch_path = res_path.clone();
-        thread::spawn(move || {
-            let mut ino = INotify::init().unwrap();
-            ino.add_watch(&watch_path, IN_CLOSE_WRITE).unwrap();
-            loop {
-                let events = ino.wait_for_events().unwrap();
-                for event in events.iter() {
-                    watch_tx.send(event.name.display().to_string()).unwrap();
-                }
-                window_proxy.wakeup_event_loop();
-            }
-        });*/
+        let watcher = FileWatcher::new(&res_path, window_proxy);
+
+        let error_program = create_program(ERROR_SHADER, &[], Path::new("error shader"), &[])
+            .expect("Failed to compile the error shader!");
+
+        reset_gl_state();
 
         let x0 = 0.0;
         let y0 = 0.0;
@@ -213,182 +350,840 @@ impl GfxContext {
 
         GfxContext {
             resource_path: res_path,
-            shared_path: shared_path,
             quad_vao_id: vao_id,
-            quad_ibo: quad_ibo,
+            quad_ibo,
+            quad_vbo,
             offset_x: 0.0,
             offset_y: 0.0,
             scale_x: 1.0,
             scale_y: 1.0,
             next_id: 0,
             programs: HashMap::new(),
-            //watch_rx: watch_rx,
+            program_cache: HashMap::new(),
+            error_program,
+            current_program: None,
+            pipeline_state: PipelineState::default(),
+            applied_state: None,
+            render_targets: HashMap::new(),
+            textures: HashMap::new(),
+            frame_fbo: 0,
+            frame_width: 0,
+            frame_height: 0,
+            current_target: None,
+            clip_stack: Vec::new(),
+            texture_bindings: Vec::new(),
+            instance_layout: InstanceLayout::Indexed,
+            shader_cache: ShaderCache::new(),
+            gpu_profiler: GpuProfiler::new(),
+            uploader: Uploader::new(upload_method),
+            watcher,
         }
     }
 
-    pub fn begin_frame(&mut self, width: u32, height: u32) {
-        /*
-        let mut files_modified = false;
-        while let Ok(_changed_file) = self.watch_rx.try_recv() {
-            files_modified = true;
-            break;
+    /// Fails if the GPU is still busy with uploads from a frame long past.
+    pub fn begin_frame(&mut self, width: u32, height: u32) -> Result<(), String> {
+        let changed_files = self.watcher.changed_files();
+        if !changed_files.is_empty() {
+            self.refresh_shaders(&changed_files);
         }
-        if files_modified {
-            self.refresh_shaders();
-        }*/
 
-        gl::depth_mask(false);
-        gl::disable(gl::STENCIL_TEST);
-        gl::disable(gl::BLEND);
         gl::bind_vertex_array(self.quad_vao_id);
         gl::bind_buffer(gl::ELEMENT_ARRAY_BUFFER, self.quad_ibo);
 
-        self.offset_x = -1.0;
-        self.offset_y = 1.0;
-        self.scale_x = 2.0 / width as f32;
-        self.scale_y = -2.0 / height as f32;
+        self.frame_fbo = gl::get_integer_v(gl::DRAW_FRAMEBUFFER_BINDING) as gl::GLuint;
+        self.frame_width = width;
+        self.frame_height = height;
+        self.current_program = None;
+        self.bind_render_target(None);
+
+        // Anything outside the context may have touched the state between
+        // frames, so the first draw sets all of it.
+        self.pipeline_state = PipelineState::default();
+        self.applied_state = None;
+
+        self.texture_bindings.clear();
+        self.instance_layout = InstanceLayout::Indexed;
+        instances::disable_instance_attributes();
+
+        self.gpu_profiler.begin_frame();
+        self.uploader.begin_frame()
+    }
+
+    /// Start a named GPU timing scope. Scopes may be nested, and
+    /// must be closed with `end_gpu_scope` before the frame ends.
+    pub fn begin_gpu_scope(&mut self, name: &str) {
+        self.gpu_profiler.begin_scope(name);
+    }
+
+    pub fn end_gpu_scope(&mut self) {
+        self.gpu_profiler.end_scope();
     }
 
+    /// Return GPU timings for frames whose queries have completed since the
+    /// last call. Results lag a few frames behind unless `wait` is set.
+    pub fn take_gpu_timings(&mut self, wait: bool) -> Vec<GpuFrameTimings> {
+        self.gpu_profiler.collect(wait);
+        self.gpu_profiler.take_results()
+    }
+
+    /// Upload `data` to a vertex texture and bind it to the sampler `name`.
     pub fn bind_vertex_texture<T>(&mut self,
-                                  texture: &VertexDataTexture,
-                                  data: &mut Vec<T>,
-                                  format: VertexTextureFormat) {
-        texture.update_and_bind(data, format);
+                                  name: &str,
+                                  texture: &mut VertexDataTexture,
+                                  data: &[T]) {
+        texture.update(data, &mut self.uploader);
+        self.bind_texture(name, texture.binding());
+    }
+
+    pub fn create_vertex_texture(&mut self, format: VertexTextureFormat) -> VertexDataTexture {
+        VertexDataTexture::new(format)
     }
 
-    pub fn create_vertex_texture(&mut self, sampler: TextureSampler) -> VertexDataTexture {
-        VertexDataTexture::new(sampler)
+    /// Create a buffer for instance data of up to `max_item_size` bytes per
+    /// item, failing if the backend isn't supported or can't hold items that
+    /// large. Programs that read it must be compiled with the backend's define.
+    pub fn create_instance_buffer(&mut self,
+                                  backend: InstanceBackend,
+                                  max_item_size: usize) -> Result<InstanceBuffer, String> {
+        InstanceBuffer::new(backend, max_item_size)
     }
 
-    pub fn create_program(&mut self, name: &str) -> ProgramId {
+    /// Upload instance data and bind it for the following draws, in place
+    /// of any instance data bound before.
+    pub fn bind_instances<T>(&mut self, buffer: &mut InstanceBuffer, data: &[T]) {
+        instances::disable_instance_attributes();
+        self.instance_layout = buffer.upload(data, &mut self.uploader);
+        if let Some(binding) = buffer.texture_binding() {
+            self.bind_texture(INSTANCE_SAMPLER, binding);
+        }
+    }
+
+    /// Bind a texture to the sampler `name` of whichever programs are used
+    /// for the rest of the frame. Texture units are assigned per program,
+    /// and each draw binds the textures its program samples from.
+    pub fn bind_texture(&mut self, name: &str, binding: TextureBinding) {
+        match self.texture_bindings.iter_mut().find(|(n, _)| n == name) {
+            Some(entry) => entry.1 = binding,
+            None => self.texture_bindings.push((name.to_owned(), binding)),
+        }
+    }
+
+    /// Create a program from a shader file, compiled with each of `defines`
+    /// defined. Asking for the same file and set of defines again returns
+    /// the same program.
+    pub fn create_program(&mut self, name: &str, defines: &[&str]) -> Result<ProgramId, ShaderError> {
         let mut shader_path = self.resource_path.clone();
         shader_path.push(name);
-        let program = new_program(shader_path, &self.shared_path).expect("Failed to compile!");
+
+        let mut defines: Vec<String> = defines.iter().map(|d| d.to_string()).collect();
+        defines.sort();
+        defines.dedup();
+
+        let key = (shader_path, defines);
+        if let Some(&id) = self.program_cache.get(&key) {
+            return Ok(id);
+        }
+
+        let program = new_program(key.0.clone(), key.1.clone(), &mut self.shader_cache)?;
         let id = ProgramId(self.next_id);
         self.next_id += 1;
         self.programs.insert(id, program);
-        id
+        self.program_cache.insert(key, id);
+        Ok(id)
+    }
+
+    /// Set a uniform of a program, checking its type against the type the
+    /// shader declares. Values persist across hot reloads. Leaves the
+    /// program bound.
+    pub fn set_uniform<V: Into<UniformValue>>(&mut self, id: ProgramId, name: &str, value: V) {
+        let value = value.into();
+        let program = self.programs.get_mut(&id).unwrap();
+
+        match program.uniform_values.iter_mut().find(|(n, _)| n == name) {
+            Some(entry) => entry.1 = value,
+            None => program.uniform_values.push((name.to_owned(), value)),
+        }
+
+        // Broken programs get their values once they reload successfully.
+        if program.program == self.error_program {
+            return;
+        }
+        gl::use_program(program.program);
+        program.apply_uniform(name, value);
     }
 
+    /// Bind a program to draw opaquely, with depth and stencil tests off.
     pub fn bind_program(&mut self, id: ProgramId) {
+        self.bind_program_with_state(id, &PipelineState::default());
+    }
+
+    /// Bind a program, along with the pipeline state its draws use.
+    pub fn bind_program_with_state(&mut self, id: ProgramId, state: &PipelineState) {
+        self.pipeline_state = *state;
+        self.current_program = Some(id);
         let program = &self.programs[&id];
         gl::use_program(program.program);
         gl::uniform_4f(program.u_transform, self.offset_x, self.offset_y, self.scale_x, self.scale_y);
     }
 
-    pub fn clear(&self, color: ColorF) {
+    /// Create an offscreen color target. Its contents start out undefined.
+    pub fn create_render_target(&mut self,
+                                width: u32,
+                                height: u32,
+                                format: RenderTargetFormat) -> Result<RenderTargetId, String> {
+        let target = RenderTarget::new(width, height, format);
+        self.restore_framebuffer();
+        let id = RenderTargetId(self.next_id);
+        self.next_id += 1;
+        self.render_targets.insert(id, target?);
+        Ok(id)
+    }
+
+    /// Reallocate a render target at a new size, discarding its contents.
+    pub fn resize_render_target(&mut self, id: RenderTargetId, width: u32, height: u32) -> Result<(), String> {
+        let result = self.render_targets.get_mut(&id).unwrap().resize(width, height);
+        self.restore_framebuffer();
+        if self.current_target == Some(id) {
+            self.bind_render_target(Some(id));
+        }
+        result
+    }
+
+    pub fn destroy_render_target(&mut self, id: RenderTargetId) {
+        if self.current_target == Some(id) {
+            self.bind_render_target(None);
+        }
+        self.render_targets.remove(&id);
+    }
+
+    pub fn render_target_size(&self, id: RenderTargetId) -> (u32, u32) {
+        let target = &self.render_targets[&id];
+        (target.width, target.height)
+    }
+
+    /// The texture of a render target, to bind to a sampler with
+    /// `bind_texture`. Targets are stored like the framebuffer, so their
+    /// top row is at v = 1.
+    pub fn render_target_binding(&self, id: RenderTargetId) -> TextureBinding {
+        TextureBinding {
+            target: gl::TEXTURE_2D,
+            id: self.render_targets[&id].texture,
+            kind: SamplerKind::Float,
+        }
+    }
+
+    /// Load a PNG, JPEG or GIF image from `path`, relative to the resource
+    /// directory. Colors are premultiplied by alpha, so draw images with
+    /// `BlendState::premultiplied_alpha`.
+    pub fn create_texture_from_file(&mut self, path: &str, options: TextureOptions) -> Result<TextureId, String> {
+        let (width, height, mut pixels) = util::load_image(&self.resource_path.join(path))?;
+        texture::premultiply(&mut pixels);
+        self.create_texture_from_rgba8(width, height, &pixels, options)
+            .map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Create a texture from top-down RGBA8 pixels, used as they are.
+    pub fn create_texture_from_rgba8(&mut self,
+                                     width: u32,
+                                     height: u32,
+                                     pixels: &[u8],
+                                     options: TextureOptions) -> Result<TextureId, String> {
+        let texture = Texture::new(width, height, Some(pixels), options)?;
+        let id = TextureId(self.next_id);
+        self.next_id += 1;
+        self.textures.insert(id, texture);
+        Ok(id)
+    }
+
+    /// Create an atlas of `page_size` square pages, for drawing many
+    /// images with one texture binding.
+    pub fn create_texture_atlas(&mut self, page_size: u32, filter: TextureFilter) -> Result<TextureAtlas, String> {
+        TextureAtlas::new(page_size, filter)
+    }
+
+    pub fn destroy_texture(&mut self, id: TextureId) {
+        self.textures.remove(&id);
+    }
+
+    pub fn texture_size(&self, id: TextureId) -> (u32, u32) {
+        let texture = &self.textures[&id];
+        (texture.width, texture.height)
+    }
+
+    /// The texture to bind to a sampler with `bind_texture`.
+    pub fn texture_binding(&self, id: TextureId) -> TextureBinding {
+        TextureBinding {
+            target: gl::TEXTURE_2D,
+            id: self.textures[&id].id,
+            kind: SamplerKind::Float,
+        }
+    }
+
+    /// Draw into a render target, or into the frame's framebuffer for None.
+    /// The viewport and the transform of programs bound from now on are
+    /// set from the target's size. The frame's framebuffer is bound again
+    /// at the start and end of each frame.
+    pub fn bind_render_target(&mut self, id: Option<RenderTargetId>) {
+        let (fbo, width, height) = self.framebuffer(id);
+        gl::bind_framebuffer(gl::FRAMEBUFFER, fbo);
+        gl::viewport(0, 0, width as gl::GLint, height as gl::GLint);
+        self.current_target = id;
+        self.clip_stack.clear();
+        self.apply_clip();
+
+        self.offset_x = -1.0;
+        self.offset_y = 1.0;
+        self.scale_x = 2.0 / width as f32;
+        self.scale_y = -2.0 / height as f32;
+
+        // The bound program keeps its pipeline state, with a new transform.
+        if let Some(id) = self.current_program {
+            let program = &self.programs[&id];
+            gl::use_program(program.program);
+            gl::uniform_4f(program.u_transform, self.offset_x, self.offset_y, self.scale_x, self.scale_y);
+        }
+    }
+
+    /// Copy a rectangle of one target to another, scaling with linear
+    /// filtering if the sizes differ. None is the frame's framebuffer.
+    pub fn blit(&mut self,
+                src: Option<RenderTargetId>,
+                src_rect: Rect,
+                dst: Option<RenderTargetId>,
+                dst_rect: Rect) {
+        let (src_fbo, _, src_height) = self.framebuffer(src);
+        let (dst_fbo, _, dst_height) = self.framebuffer(dst);
+
+        // Flip the rectangles to GL's bottom left origin.
+        let src_y = src_height as i32 - src_rect.y - src_rect.height;
+        let dst_y = dst_height as i32 - dst_rect.y - dst_rect.height;
+        let filter = if src_rect.width == dst_rect.width && src_rect.height == dst_rect.height {
+            gl::NEAREST
+        } else {
+            gl::LINEAR
+        };
+
+        // Blits are scissored, but aren't clipped like draws.
+        gl::disable(gl::SCISSOR_TEST);
+        gl::bind_framebuffer(gl::READ_FRAMEBUFFER, src_fbo);
+        gl::bind_framebuffer(gl::DRAW_FRAMEBUFFER, dst_fbo);
+        unsafe {
+            gl::BlitFramebuffer(src_rect.x,
+                                src_y,
+                                src_rect.x + src_rect.width,
+                                src_y + src_rect.height,
+                                dst_rect.x,
+                                dst_y,
+                                dst_rect.x + dst_rect.width,
+                                dst_y + dst_rect.height,
+                                gl::COLOR_BUFFER_BIT,
+                                filter);
+        }
+        self.restore_framebuffer();
+        self.apply_clip();
+    }
+
+    fn framebuffer(&self, id: Option<RenderTargetId>) -> (gl::GLuint, u32, u32) {
+        match id {
+            Some(id) => {
+                let target = &self.render_targets[&id];
+                (target.fbo, target.width, target.height)
+            }
+            None => (self.frame_fbo, self.frame_width, self.frame_height),
+        }
+    }
+
+    /// Restrict clears and draws to `rect` within the current clip, until
+    /// the matching `pop_clip_rect`. Binding a render target empties the
+    /// clip stack.
+    pub fn push_clip_rect(&mut self, rect: Rect) {
+        let rect = match self.clip_stack.last() {
+            Some(clip) => clip.intersection(&rect),
+            None => rect,
+        };
+        self.clip_stack.push(rect);
+        self.apply_clip();
+    }
+
+    pub fn pop_clip_rect(&mut self) {
+        self.clip_stack.pop().expect("pop_clip_rect without a matching push_clip_rect");
+        self.apply_clip();
+    }
+
+    // Set the scissor rect from the top of the clip stack, flipping it to
+    // GL's bottom left origin.
+    fn apply_clip(&self) {
+        match self.clip_stack.last() {
+            Some(clip) => {
+                let (_, _, height) = self.framebuffer(self.current_target);
+                gl::enable(gl::SCISSOR_TEST);
+                gl::scissor(clip.x,
+                            height as i32 - clip.y - clip.height,
+                            clip.width,
+                            clip.height);
+            }
+            None => gl::disable(gl::SCISSOR_TEST),
+        }
+    }
+
+    fn restore_framebuffer(&self) {
+        let (fbo, _, _) = self.framebuffer(self.current_target);
+        gl::bind_framebuffer(gl::FRAMEBUFFER, fbo);
+    }
+
+    /// Clear every channel of the color buffer, whatever the color mask.
+    pub fn clear(&mut self, color: ColorF) {
+        self.set_color_mask([true; 4]);
         gl::clear_color(color.r, color.g, color.b, color.a);
         gl::clear(gl::COLOR_BUFFER_BIT);
+    }
 
+    /// Clear the depth and stencil buffers of the frame's framebuffer.
+    /// Render targets have neither.
+    pub fn clear_depth_stencil(&mut self, depth: f32, stencil: i32) {
+        // Clears are masked by the depth and stencil write masks. Enabling
+        // the tests as well doesn't affect the clear.
+        let state = PipelineState {
+            depth_write: true,
+            stencil: Some(StencilState::write_all()),
+            ..self.applied_state.unwrap_or_default()
+        };
+        state.apply(self.applied_state.as_ref());
+        self.applied_state = Some(state);
+        gl::clear_depth(depth as f64);
+        gl::clear_stencil(stencil);
+        gl::clear(gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
     }
 
+    fn set_color_mask(&mut self, color_mask: [bool; 4]) {
+        let state = PipelineState {
+            color_mask,
+            ..self.applied_state.unwrap_or_default()
+        };
+        state.apply(self.applied_state.as_ref());
+        self.applied_state = Some(state);
+    }
+
+    // Set the GL state that differs from what the next draw needs.
+    fn apply_pipeline_state(&mut self) {
+        if self.applied_state != Some(self.pipeline_state) {
+            self.pipeline_state.apply(self.applied_state.as_ref());
+            self.applied_state = Some(self.pipeline_state);
+        }
+    }
+
+    /// Draw `count` instanced quads with the bound program. The draw is
+    /// skipped if the program samples from a texture that isn't bound.
     pub fn draw_quads(&mut self, count: usize) {
-        gl::draw_elements_instanced(gl::TRIANGLES,
-                                    6,
-                                    gl::UNSIGNED_SHORT,
-                                    0,
-                                    count as gl::GLint);
+        self.draw_quads_range(0, count);
+    }
+
+    /// Draw quads for `count` of the bound instances, starting at `first`,
+    /// so that one upload can serve draws with different textures.
+    pub fn draw_quads_range(&mut self, first: usize, count: usize) {
+        let mut u_first_instance = -1;
+        if let Some(id) = self.current_program {
+            let program = self.programs.get_mut(&id).unwrap();
+            if !program.bind_samplers(&self.texture_bindings) {
+                return;
+            }
+            u_first_instance = program.u_first_instance;
+        }
+        self.apply_pipeline_state();
+
+        match self.instance_layout {
+            InstanceLayout::Indexed => {
+                gl::uniform_1i(u_first_instance, first as gl::GLint);
+                draw_instanced_quads(count);
+            }
+            // Uniform buffers only hold a block of instances per draw. Blocks
+            // start at multiples of the block size, which are aligned.
+            InstanceLayout::UniformBlocks { buffer, items_per_block, item_size } => {
+                let end = first + count;
+                let mut first = first;
+                while first < end {
+                    let block_start = first / items_per_block * items_per_block;
+                    let offset = first - block_start;
+                    let items = cmp::min(items_per_block - offset, end - first);
+                    unsafe {
+                        gl::BindBufferRange(gl::UNIFORM_BUFFER,
+                                            0,
+                                            buffer,
+                                            (block_start * item_size) as gl::GLintptr,
+                                            ((offset + items) * item_size) as gl::GLsizeiptr);
+                    }
+                    gl::uniform_1i(u_first_instance, offset as gl::GLint);
+                    draw_instanced_quads(items);
+                    first += items;
+                }
+            }
+            InstanceLayout::Attributes { buffer, vecs_per_item } => {
+                gl::bind_buffer(gl::ARRAY_BUFFER, buffer);
+                instances::set_instance_attributes(vecs_per_item, first);
+                draw_instanced_quads(count);
+            }
+        }
     }
 
-    pub fn refresh_shaders(&mut self) {
-        for (_, program) in &mut self.programs {
-            let new_program = new_program(program.path.clone(), &self.shared_path);
-            if let Some(new_program) = new_program {
+    /// Recompile the programs that depend on any of the changed files.
+    /// Programs that fail to build draw with the error shader until fixed.
+    pub fn refresh_shaders(&mut self, changed_files: &[PathBuf]) {
+        for program in self.programs.values_mut() {
+            if !program.dependencies.iter().any(|d| changed_files.contains(d)) {
+                continue;
+            }
+            if program.program != self.error_program {
                 gl::delete_program(program.program);
-                *program = new_program;
-            } else {
-                println!("Failed to compile {:?}, using old shader!", program.path);
             }
+            match new_program(program.path.clone(), program.defines.clone(), &mut self.shader_cache) {
+                Ok(new_program) => {
+                    let uniform_values = mem::take(&mut program.uniform_values);
+                    *program = new_program;
+                    gl::use_program(program.program);
+                    for (name, value) in &uniform_values {
+                        program.apply_uniform(name, *value);
+                    }
+                    gl::use_program(0);
+                    program.uniform_values = uniform_values;
+                    eprintln!("Reloaded {}", program.path.display());
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    // Describe the program that is live now, so nothing is
+                    // looked up in the deleted one.
+                    program.program = self.error_program;
+                    program.u_transform = -1;
+                    program.u_first_instance = -1;
+                    program.reflection = ProgramReflection::query(self.error_program);
+                    program.samplers.clear();
+                    // Keep watching any files the broken version added.
+                    for file in e.files {
+                        if !program.dependencies.contains(&file) {
+                            program.dependencies.push(file);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Data uploaded since this context was created.
+    pub fn upload_stats(&self) -> UploadStats {
+        self.uploader.stats
+    }
+
+    /// Program build times since this context was created.
+    pub fn shader_stats(&self) -> ShaderStats {
+        self.shader_cache.stats
+    }
+
+    /// Read back the current framebuffer as tightly packed RGBA8 rows,
+    /// top row first. Call before swapping buffers.
+    pub fn read_pixels(&self, width: u32, height: u32) -> Vec<u8> {
+        gl::pixel_store_i(gl::PACK_ALIGNMENT, 1);
+        let pixels = gl::read_pixels(0, 0,
+                                     width as gl::GLsizei,
+                                     height as gl::GLsizei,
+                                     gl::RGBA,
+                                     gl::UNSIGNED_BYTE);
+
+        // GL returns the bottom row first.
+        let stride = width as usize * 4;
+        let mut flipped = Vec::with_capacity(pixels.len());
+        for row in pixels.chunks(stride).rev() {
+            flipped.extend_from_slice(row);
         }
+        flipped
     }
 
     pub fn end_frame(&mut self) {
+        self.bind_render_target(None);
+        self.uploader.end_frame();
+        self.gpu_profiler.end_frame();
+    }
+}
+
+impl Drop for GfxContext {
+    fn drop(&mut self) {
+        for program in self.programs.values() {
+            if program.program != self.error_program {
+                gl::delete_program(program.program);
+            }
+        }
+        gl::delete_program(self.error_program);
+        gl::delete_vertex_arrays(&[self.quad_vao_id]);
+        gl::delete_buffers(&[self.quad_ibo, self.quad_vbo]);
+        reset_gl_state();
+    }
+}
+
+fn draw_instanced_quads(count: usize) {
+    gl::draw_elements_instanced(gl::TRIANGLES,
+                                6,
+                                gl::UNSIGNED_SHORT,
+                                0,
+                                count as gl::GLint);
+}
+
+// Put the GL state that apps may touch back to its defaults, so that
+// apps run one after another in the same context don't affect each other.
+fn reset_gl_state() {
+    gl::use_program(0);
+    gl::bind_vertex_array(0);
+    gl::bind_buffer(gl::ARRAY_BUFFER, 0);
+    gl::bind_buffer(gl::ELEMENT_ARRAY_BUFFER, 0);
+
+    let texture_units = gl::get_integer_v(gl::MAX_COMBINED_TEXTURE_IMAGE_UNITS);
+    for unit in 0..texture_units as gl::GLuint {
+        gl::active_texture(gl::TEXTURE0 + unit);
+        gl::bind_texture(gl::TEXTURE_2D, 0);
+    }
+    gl::active_texture(gl::TEXTURE0);
+
+    gl::disable(gl::BLEND);
+    gl::disable(gl::DEPTH_TEST);
+    gl::disable(gl::STENCIL_TEST);
+    gl::disable(gl::SCISSOR_TEST);
+    gl::disable(gl::CULL_FACE);
+    gl::blend_func(gl::ONE, gl::ZERO);
+    gl::depth_mask(true);
+    gl::color_mask(true, true, true, true);
+    gl::clear_color(0.0, 0.0, 0.0, 0.0);
+}
+
+// Drawn in place of programs that fail to reload. Covers the whole target
+// with a magenta checkerboard, whatever instance data the app binds.
+const ERROR_SHADER: &str = "
+#ifdef VERTEX_SHADER
+in vec2 aPosition;
+void main() {
+    gl_Position = vec4(aPosition * 2.0 - 1.0, 0.0, 1.0);
+}
+#endif
+#ifdef FRAGMENT_SHADER
+out vec4 oFragColor;
+void main() {
+    vec2 cell = floor(gl_FragCoord.xy / 16.0);
+    bool magenta = mod(cell.x + cell.y, 2.0) < 1.0;
+    oFragColor = magenta ? vec4(1.0, 0.0, 1.0, 1.0) : vec4(0.0, 0.0, 0.0, 1.0);
+}
+#endif
+";
+
+// oFragBlend is the second source of dual source blending, which needs
+// GL 3.3 or ARB_blend_func_extended. Shaders opt in by defining
+// DUAL_SOURCE_BLENDING.
+fn uses_dual_source_blending(defines: &[String]) -> bool {
+    defines.iter().any(|d| d == "DUAL_SOURCE_BLENDING")
+}
+
+fn bind_frag_data_locations(program: gl::GLuint, defines: &[String]) {
+    unsafe {
+        if uses_dual_source_blending(defines) {
+            gl::BindFragDataLocationIndexed(program, 0, 0, "oFragColor\0".as_ptr() as *const gl::GLchar);
+            gl::BindFragDataLocationIndexed(program, 0, 1, "oFragBlend\0".as_ptr() as *const gl::GLchar);
+        } else {
+            gl::BindFragDataLocation(program, 0, "oFragColor\0".as_ptr() as *const gl::GLchar);
+        }
     }
 }
 
 fn compile_shader(source: &str,
-                  defines: &str,
-                  shared: &str,
-                  shader_type: gl::GLenum) -> Option<gl::GLuint> {
+                  defines: &[String],
+                  stage: ShaderStage) -> Result<gl::GLuint, String> {
     let version = "#version 150\n";
+    let (stage_define, shader_type) = match stage {
+        ShaderStage::Vertex => ("#define VERTEX_SHADER\n", gl::VERTEX_SHADER),
+        ShaderStage::Fragment => ("#define FRAGMENT_SHADER\n", gl::FRAGMENT_SHADER),
+    };
     let id = gl::create_shader(shader_type);
 
     let mut src = Vec::new();
     src.extend_from_slice(version.as_bytes());
-    src.extend_from_slice(defines.as_bytes());
-    src.extend_from_slice(shared.as_bytes());
+    for extension in instances::shader_extensions(defines) {
+        src.extend_from_slice(format!("#extension {} : require\n", extension).as_bytes());
+    }
+    src.extend_from_slice(stage_define.as_bytes());
+    for define in defines {
+        src.extend_from_slice(format!("#define {}\n", define).as_bytes());
+    }
     src.extend_from_slice(source.as_bytes());
     gl::shader_source(id, &[&src[..]]);
 
     gl::compile_shader(id);
     if gl::get_shader_iv(id, gl::COMPILE_STATUS) == (0 as gl::GLint) {
-        println!("Failed to compile shader: {}", gl::get_shader_info_log(id));
-        None
+        let log = gl::get_shader_info_log(id);
+        gl::delete_shader(id);
+        Err(log)
     } else {
-        Some(id)
+        Ok(id)
     }
 }
 
+/// Compile both stages of `source` and link them. `files` maps the source
+/// string numbers in driver logs back to file paths.
 fn create_program(source: &str,
-                  shared: &str) -> Option<gl::GLuint> {
-    let vs_defines = "#define VERTEX_SHADER\n";
-    let fs_defines = "#define FRAGMENT_SHADER\n";
-    let mut program = None;
-
-    let vs_id = compile_shader(source,
-                               vs_defines,
-                               shared,
-                               gl::VERTEX_SHADER);
-
-    if let Some(vs_id) = vs_id {
-        let fs_id = compile_shader(source,
-                                   fs_defines,
-                                   shared,
-                                   gl::FRAGMENT_SHADER);
-
-        if let Some(fs_id) = fs_id {
-            let pid = gl::create_program();
-
-            gl::attach_shader(pid, vs_id);
-            gl::attach_shader(pid, fs_id);
-
-            gl::bind_attrib_location(pid, VertexAttribute::Position as gl::GLuint, "aPosition");
-
-            gl::link_program(pid);
-
-            if gl::get_program_iv(pid, gl::LINK_STATUS) == (0 as gl::GLint) {
-                println!("Failed to link shader program: {}", gl::get_program_info_log(pid));
-                gl::detach_shader(pid, vs_id);
-                gl::detach_shader(pid, fs_id);
-            } else {
-                program = Some(pid);
-            }
-        }
+                  defines: &[String],
+                  path: &Path,
+                  files: &[PathBuf]) -> Result<gl::GLuint, ShaderError> {
+    if uses_dual_source_blending(defines) &&
+       util::gl_version() < (3, 3) &&
+       !util::has_extension("GL_ARB_blend_func_extended") {
+        let message = "dual source blending needs GL 3.3 or GL_ARB_blend_func_extended";
+        return Err(ShaderError::from_log(ShaderErrorKind::Link, path, message, files));
     }
 
-    program
+    let compile = |stage| {
+        compile_shader(source, defines, stage).map_err(|log| {
+            ShaderError::from_log(ShaderErrorKind::Compile(stage), path, &log, files)
+        })
+    };
+
+    let vs_id = compile(ShaderStage::Vertex)?;
+    let fs_id = match compile(ShaderStage::Fragment) {
+        Ok(fs_id) => fs_id,
+        Err(e) => {
+            gl::delete_shader(vs_id);
+            return Err(e);
+        }
+    };
+
+    let pid = gl::create_program();
+
+    gl::attach_shader(pid, vs_id);
+    gl::attach_shader(pid, fs_id);
+
+    gl::bind_attrib_location(pid, VertexAttribute::Position as gl::GLuint, "aPosition");
+    bind_frag_data_locations(pid, defines);
+    instances::bind_attribute_locations(pid);
+
+    shader_cache::set_retrievable(pid);
+    gl::link_program(pid);
+
+    // The shaders aren't needed once the program is linked.
+    gl::detach_shader(pid, vs_id);
+    gl::detach_shader(pid, fs_id);
+    gl::delete_shader(vs_id);
+    gl::delete_shader(fs_id);
+
+    if gl::get_program_iv(pid, gl::LINK_STATUS) == (0 as gl::GLint) {
+        let log = gl::get_program_info_log(pid);
+        gl::delete_program(pid);
+        Err(ShaderError::from_log(ShaderErrorKind::Link, path, &log, files))
+    } else {
+        Ok(pid)
+    }
 }
 
-fn new_program(path: PathBuf, shared_path: &PathBuf) -> Option<Program> {
-    let shared = util::load_text_file(shared_path);
-    let shader_source = util::load_text_file(&path);
-    let program = create_program(&shader_source, &shared);
-    program.map(|program| {
-        gl::use_program(program);
-        let u_transform = gl::get_uniform_location(program, "uTransform");
-        let u_float0 = gl::get_uniform_location(program, "sFloat0");
-        if u_float0 != -1 {
-            gl::uniform_1i(u_float0, TextureSampler::Float0 as gl::GLint);
-        }
-        let u_float1 = gl::get_uniform_location(program, "sFloat1");
-        if u_float1 != -1 {
-            gl::uniform_1i(u_float1, TextureSampler::Float1 as gl::GLint);
-        }
-        gl::use_program(0);
-        Program {
-            path: path,
-            program: program,
-            u_transform: u_transform,
+fn new_program(path: PathBuf,
+               defines: Vec<String>,
+               cache: &mut ShaderCache) -> Result<Program, ShaderError> {
+    let source = preprocessor::preprocess(&path).map_err(|e| {
+        ShaderError::preprocess(&path, &e).with_defines(&defines)
+    })?;
+    let program = cache.get_or_link(&path, &source.text, &defines, || {
+        create_program(&source.text, &defines, &path, &source.files)
+    }).map_err(|e| e.with_defines(&defines))?;
+
+    let reflection = ProgramReflection::query(program);
+
+    // Give each sampler the program declares its own texture unit.
+    gl::use_program(program);
+    let u_transform = gl::get_uniform_location(program, "uTransform");
+    let u_first_instance = gl::get_uniform_location(program, "uFirstInstance");
+    let mut samplers = Vec::new();
+    for uniform in &reflection.uniforms {
+        if let Some((kind, target)) = reflection::sampler_type(uniform.ty) {
+            let unit = samplers.len() as gl::GLuint;
+            gl::uniform_1i(uniform.location, unit as gl::GLint);
+            samplers.push(SamplerSlot {
+                name: uniform.name.clone(),
+                ty: uniform.ty,
+                kind,
+                target,
+                unit,
+            });
         }
+    }
+    gl::use_program(0);
+
+    Ok(Program {
+        dependencies: source.files,
+        path,
+        defines,
+        program,
+        u_transform,
+        u_first_instance,
+        reflection,
+        samplers,
+        uniform_values: Vec::new(),
+        warned: Vec::new(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::GfxContext;
+    use backend::Backend;
+    use pipeline::{BlendState, PipelineState};
+    use render_target::RenderTargetFormat;
+    use upload::UploadMethod;
+
+    #[test]
+    fn binding_a_render_target_keeps_the_pipeline_state() {
+        let backend = match Backend::new_headless(64, 64) {
+            Ok(backend) => backend,
+            Err(e) => {
+                eprintln!("skipping, no headless GL context: {}", e);
+                return;
+            }
+        };
+        let mut gfx = GfxContext::new(None, UploadMethod::Direct);
+        gfx.begin_frame(64, 64).unwrap();
+
+        let program = gfx.create_program("solid.glsl", &["INSTANCES_TEXTURE"]).unwrap();
+        let blended = PipelineState::blended(BlendState::premultiplied_alpha());
+        gfx.bind_program_with_state(program, &blended);
+
+        let target = gfx.create_render_target(32, 32, RenderTargetFormat::Rgba8).unwrap();
+        gfx.bind_render_target(Some(target));
+        assert_eq!(gfx.pipeline_state, blended);
+        assert!(gfx.current_program == Some(program));
+        assert_eq!(gfx.scale_x, 2.0 / 32.0);
+
+        gfx.bind_render_target(None);
+        assert_eq!(gfx.pipeline_state, blended);
+        assert_eq!(gfx.scale_x, 2.0 / 64.0);
+
+        gfx.destroy_render_target(target);
+        gfx.end_frame();
+        drop(gfx);
+        drop(backend);
+    }
+
+    #[test]
+    fn a_rejected_resize_keeps_the_render_target_size() {
+        let backend = match Backend::new_headless(64, 64) {
+            Ok(backend) => backend,
+            Err(e) => {
+                eprintln!("skipping, no headless GL context: {}", e);
+                return;
+            }
+        };
+        let mut gfx = GfxContext::new(None, UploadMethod::Direct);
+        gfx.begin_frame(64, 64).unwrap();
+
+        let target = gfx.create_render_target(32, 16, RenderTargetFormat::Rgba8).unwrap();
+        assert!(gfx.resize_render_target(target, 0, 16).is_err());
+        assert!(gfx.resize_render_target(target, 32, u32::MAX).is_err());
+        assert_eq!(gfx.render_target_size(target), (32, 16));
+
+        gfx.resize_render_target(target, 48, 24).unwrap();
+        assert_eq!(gfx.render_target_size(target), (48, 24));
+
+        gfx.destroy_render_target(target);
+        gfx.end_frame();
+        drop(gfx);
+        drop(backend);
+    }
+}
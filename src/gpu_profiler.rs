@@ -0,0 +1,176 @@
+use gleam::gl;
+use std::mem;
+
+// Number of frames of queries in flight. Results are read back this many
+// frames after they were issued, by which point the GPU has almost always
+// finished with them, so reading them does not stall the pipeline.
+const QUERY_FRAMES: usize = 4;
+
+/// GPU time spent on a frame and on each named scope within it.
+#[derive(Debug, Clone)]
+pub struct GpuFrameTimings {
+    pub frame_index: usize,
+    pub total_ns: u64,
+    pub scopes: Vec<(String, u64)>,
+}
+
+struct GpuScope {
+    name: String,
+    begin_query: usize,
+    end_query: usize,
+}
+
+struct QueryFrame {
+    frame_index: usize,
+    pending: bool,
+    timestamp_queries: Vec<gl::GLuint>,
+    used_timestamps: usize,
+    scopes: Vec<GpuScope>,
+}
+
+impl QueryFrame {
+    fn new() -> QueryFrame {
+        QueryFrame {
+            frame_index: 0,
+            pending: false,
+            timestamp_queries: Vec::new(),
+            used_timestamps: 0,
+            scopes: Vec::new(),
+        }
+    }
+
+    fn add_timestamp(&mut self) -> usize {
+        if self.used_timestamps == self.timestamp_queries.len() {
+            self.timestamp_queries.push(gl::gen_queries(1)[0]);
+        }
+        let index = self.used_timestamps;
+        gl::query_counter(self.timestamp_queries[index], gl::TIMESTAMP);
+        self.used_timestamps += 1;
+        index
+    }
+
+    fn elapsed(&self, begin_query: usize, end_query: usize) -> u64 {
+        let begin = gl::get_query_object_ui64v(self.timestamp_queries[begin_query], gl::QUERY_RESULT);
+        let end = gl::get_query_object_ui64v(self.timestamp_queries[end_query], gl::QUERY_RESULT);
+        end.saturating_sub(begin)
+    }
+
+    // Queries complete in order, so the frame is done once its last one is.
+    fn is_available(&self) -> bool {
+        let last = self.timestamp_queries[self.used_timestamps - 1];
+        gl::get_query_object_iv(last, gl::QUERY_RESULT_AVAILABLE) != 0
+    }
+
+    // Blocks until the results are available.
+    fn read(&mut self) -> GpuFrameTimings {
+        debug_assert!(self.pending);
+        self.pending = false;
+
+        let scopes = self.scopes.iter().map(|scope| {
+            (scope.name.clone(), self.elapsed(scope.begin_query, scope.end_query))
+        }).collect();
+
+        GpuFrameTimings {
+            frame_index: self.frame_index,
+            total_ns: self.elapsed(0, self.used_timestamps - 1),
+            scopes,
+        }
+    }
+}
+
+impl Drop for QueryFrame {
+    fn drop(&mut self) {
+        gl::delete_queries(&self.timestamp_queries);
+    }
+}
+
+/// Measures GPU execution time with a ring of timer queries.
+///
+/// Frames and named scopes are measured with pairs of `GL_TIMESTAMP`
+/// queries. Unlike `GL_TIME_ELAPSED` queries these may be nested, and
+/// some drivers (llvmpipe) report a bogus first `GL_TIME_ELAPSED` result.
+pub struct GpuProfiler {
+    frames: Vec<QueryFrame>,
+    current: usize,
+    frame_index: usize,
+    open_scopes: Vec<(String, usize)>,
+    results: Vec<GpuFrameTimings>,
+}
+
+impl GpuProfiler {
+    pub fn new() -> GpuProfiler {
+        GpuProfiler {
+            frames: (0..QUERY_FRAMES).map(|_| QueryFrame::new()).collect(),
+            current: 0,
+            frame_index: 0,
+            open_scopes: Vec::new(),
+            results: Vec::new(),
+        }
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.collect(false);
+
+        let frame = &mut self.frames[self.current];
+        if frame.pending {
+            let timings = frame.read();
+            self.results.push(timings);
+        }
+
+        frame.frame_index = self.frame_index;
+        frame.used_timestamps = 0;
+        frame.scopes.clear();
+        frame.add_timestamp();
+    }
+
+    pub fn end_frame(&mut self) {
+        debug_assert!(self.open_scopes.is_empty(), "unbalanced GPU scopes");
+        self.open_scopes.clear();
+
+        let frame = &mut self.frames[self.current];
+        frame.add_timestamp();
+        frame.pending = true;
+        self.current = (self.current + 1) % QUERY_FRAMES;
+        self.frame_index += 1;
+    }
+
+    pub fn begin_scope(&mut self, name: &str) {
+        let name = match self.open_scopes.last() {
+            Some((parent, _)) => format!("{}/{}", parent, name),
+            None => name.to_owned(),
+        };
+        let begin_query = self.frames[self.current].add_timestamp();
+        self.open_scopes.push((name, begin_query));
+    }
+
+    pub fn end_scope(&mut self) {
+        let (name, begin_query) = self.open_scopes.pop().expect("end_gpu_scope without begin");
+        let frame = &mut self.frames[self.current];
+        let end_query = frame.add_timestamp();
+        frame.scopes.push(GpuScope {
+            name,
+            begin_query,
+            end_query,
+        });
+    }
+
+    /// Read back the results of completed frames, oldest first. If `wait`
+    /// is set, block until every submitted frame has completed.
+    pub fn collect(&mut self, wait: bool) {
+        for i in 0..QUERY_FRAMES {
+            let frame = &mut self.frames[(self.current + i) % QUERY_FRAMES];
+            if !frame.pending {
+                continue;
+            }
+            if !wait && !frame.is_available() {
+                break;
+            }
+            let timings = frame.read();
+            self.results.push(timings);
+        }
+    }
+
+    pub fn take_results(&mut self) -> Vec<GpuFrameTimings> {
+        mem::take(&mut self.results)
+    }
+}
@@ -0,0 +1,91 @@
+use app::{Event, KeyCode, Modifiers, ScrollDelta};
+use glutin::{self, ElementState, MouseScrollDelta};
+
+/// Translates glutin events into app events. glutin does not report
+/// modifier state, the cursor position with button presses or scale
+/// factor changes, so those are tracked here.
+pub struct InputState {
+    modifiers: Modifiers,
+    mouse_x: i32,
+    mouse_y: i32,
+    scale_factor: f32,
+}
+
+impl InputState {
+    pub fn new(window: &glutin::Window) -> InputState {
+        InputState {
+            modifiers: Modifiers::default(),
+            mouse_x: 0,
+            mouse_y: 0,
+            scale_factor: window.hidpi_factor(),
+        }
+    }
+
+    fn update_modifiers(&mut self, key: KeyCode, pressed: bool) {
+        match key {
+            KeyCode::LShift | KeyCode::RShift => self.modifiers.shift = pressed,
+            KeyCode::LControl | KeyCode::RControl => self.modifiers.ctrl = pressed,
+            KeyCode::LAlt | KeyCode::RAlt => self.modifiers.alt = pressed,
+            KeyCode::LWin | KeyCode::RWin => self.modifiers.logo = pressed,
+            _ => {}
+        }
+    }
+
+    /// Returns the events corresponding to a glutin event, if any.
+    pub fn translate(&mut self, window: &glutin::Window, event: glutin::Event) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        match event {
+            glutin::Event::KeyboardInput(state, scancode, key) => {
+                let pressed = state == ElementState::Pressed;
+                if let Some(key) = key {
+                    self.update_modifiers(key, pressed);
+                }
+                events.push(if pressed {
+                    Event::KeyDown { key, scancode, modifiers: self.modifiers }
+                } else {
+                    Event::KeyUp { key, scancode, modifiers: self.modifiers }
+                });
+            }
+            glutin::Event::ReceivedCharacter(c) => events.push(Event::Character(c)),
+            glutin::Event::MouseMoved(x, y) => {
+                self.mouse_x = x;
+                self.mouse_y = y;
+                events.push(Event::MouseMove { x, y });
+            }
+            glutin::Event::MouseInput(state, button) => {
+                let (x, y, modifiers) = (self.mouse_x, self.mouse_y, self.modifiers);
+                events.push(match state {
+                    ElementState::Pressed => Event::MouseDown { button, x, y, modifiers },
+                    ElementState::Released => Event::MouseUp { button, x, y, modifiers },
+                });
+            }
+            glutin::Event::MouseWheel(delta, _) => {
+                let delta = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => ScrollDelta::Lines(x, y),
+                    MouseScrollDelta::PixelDelta(x, y) => ScrollDelta::Pixels(x, y),
+                };
+                events.push(Event::MouseWheel { delta, modifiers: self.modifiers });
+            }
+            glutin::Event::Resized(width, height) => {
+                // Moving to a monitor with a different DPI resizes the window.
+                let scale_factor = window.hidpi_factor();
+                if scale_factor != self.scale_factor {
+                    self.scale_factor = scale_factor;
+                    events.push(Event::ScaleFactor(scale_factor));
+                }
+                events.push(Event::Resize { width, height });
+            }
+            glutin::Event::Focused(focused) => {
+                // Keys released while unfocused are never reported.
+                if !focused {
+                    self.modifiers = Modifiers::default();
+                }
+                events.push(Event::Focus(focused));
+            }
+            _ => {}
+        }
+
+        events
+    }
+}
@@ -0,0 +1,312 @@
+use gfx::{TextureBinding, VertexDataTexture, VertexTextureFormat};
+use gleam::gl;
+use reflection::SamplerKind;
+use std::cmp;
+use std::mem;
+use std::ptr;
+use std::slice;
+use upload::Uploader;
+use util;
+
+// SSBOs are GL 4.3 / ARB_shader_storage_buffer_object, which the GL 3.3
+// bindings don't include.
+const SHADER_STORAGE_BUFFER: gl::GLenum = 0x90D2;
+const MAX_VERTEX_SHADER_STORAGE_BLOCKS: gl::GLenum = 0x90D6;
+
+/// Matches MAX_INSTANCE_UNIFORM_VECS in shared.glsl. 16KB is the smallest
+/// uniform block size GL allows.
+const MAX_INSTANCE_UNIFORM_VECS: usize = 1024;
+
+/// Matches the size of aInstance in shared.glsl. Attribute 0 is aPosition.
+const FIRST_INSTANCE_ATTRIBUTE: gl::GLuint = 1;
+const MAX_INSTANCE_ATTRIBUTES: usize = 4;
+
+/// How instance data reaches the vertex shader. Shaders are compiled with
+/// the backend's define and read the data with `fetch_instance`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InstanceBackend {
+    /// A 2D float texture, read with texelFetch.
+    Texture,
+    /// A uniform buffer. Draws are split into blocks of at most 16KB.
+    UniformBuffer,
+    /// A buffer texture. Limited to GL_MAX_TEXTURE_BUFFER_SIZE vec4s.
+    TextureBuffer,
+    /// A shader storage buffer, where supported.
+    StorageBuffer,
+    /// Vertex attributes with a divisor of one. At most four vec4s per instance.
+    Attributes,
+}
+
+impl InstanceBackend {
+    pub const NAMES: &'static [&'static str] = &["texture", "ubo", "tbo", "ssbo", "attributes"];
+
+    pub fn from_name(name: &str) -> Option<InstanceBackend> {
+        match name {
+            "texture" => Some(InstanceBackend::Texture),
+            "ubo" => Some(InstanceBackend::UniformBuffer),
+            "tbo" => Some(InstanceBackend::TextureBuffer),
+            "ssbo" => Some(InstanceBackend::StorageBuffer),
+            "attributes" => Some(InstanceBackend::Attributes),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            InstanceBackend::Texture => "texture",
+            InstanceBackend::UniformBuffer => "ubo",
+            InstanceBackend::TextureBuffer => "tbo",
+            InstanceBackend::StorageBuffer => "ssbo",
+            InstanceBackend::Attributes => "attributes",
+        }
+    }
+
+    /// The define that selects this backend's `fetch_instance` in shared.glsl.
+    pub fn define(&self) -> &'static str {
+        match *self {
+            InstanceBackend::Texture => "INSTANCES_TEXTURE",
+            InstanceBackend::UniformBuffer => "INSTANCES_UBO",
+            InstanceBackend::TextureBuffer => "INSTANCES_TBO",
+            InstanceBackend::StorageBuffer => "INSTANCES_SSBO",
+            InstanceBackend::Attributes => "INSTANCES_ATTRIBUTES",
+        }
+    }
+
+    /// The GLSL extension that shaders compiled with the backend's define
+    /// enable, since they are GLSL 1.50.
+    pub fn extension(&self) -> Option<&'static str> {
+        match *self {
+            InstanceBackend::StorageBuffer => Some("GL_ARB_shader_storage_buffer_object"),
+            _ => None,
+        }
+    }
+
+    /// Requires a current GL context.
+    pub fn is_supported(&self) -> bool {
+        if let Some(extension) = self.extension() {
+            if !util::has_extension(extension) {
+                return false;
+            }
+        }
+        match *self {
+            InstanceBackend::StorageBuffer => gl::get_integer_v(MAX_VERTEX_SHADER_STORAGE_BLOCKS) > 0,
+            _ => true,
+        }
+    }
+}
+
+/// The extensions needed by the instance backends selected in `defines`.
+pub fn shader_extensions(defines: &[String]) -> Vec<&'static str> {
+    InstanceBackend::NAMES.iter()
+        .filter_map(|name| InstanceBackend::from_name(name))
+        .filter(|backend| defines.iter().any(|define| define == backend.define()))
+        .filter_map(|backend| backend.extension())
+        .collect()
+}
+
+/// Enable the instance attributes for items of `vecs_per_item` vec4s read
+/// from the bound array buffer, starting at item `first`, and disable the
+/// rest. The quad VAO must be bound.
+pub fn set_instance_attributes(vecs_per_item: usize, first: usize) {
+    let stride = vecs_per_item * 16;
+    for i in 0..MAX_INSTANCE_ATTRIBUTES {
+        let index = FIRST_INSTANCE_ATTRIBUTE + i as gl::GLuint;
+        if i < vecs_per_item {
+            let offset = first * stride + i * 16;
+            gl::enable_vertex_attrib_array(index);
+            gl::vertex_attrib_pointer(index, 4, gl::FLOAT, false, stride as gl::GLint, offset as gl::GLuint);
+            gl::vertex_attrib_divisor(index, 1);
+        } else {
+            gl::disable_vertex_attrib_array(index);
+        }
+    }
+}
+
+pub fn disable_instance_attributes() {
+    set_instance_attributes(0, 0);
+}
+
+/// Bind the attribute array locations used by the attributes backend.
+pub fn bind_attribute_locations(program: gl::GLuint) {
+    gl::bind_attrib_location(program, FIRST_INSTANCE_ATTRIBUTE, "aInstance");
+}
+
+/// Where draws find uploaded instances, so that they can start part way
+/// through them.
+#[derive(Copy, Clone)]
+pub enum InstanceLayout {
+    /// Shaders read every instance, counting from `uFirstInstance`.
+    Indexed,
+    /// Draws are split into blocks of uniform buffer instances, and shaders
+    /// count from `uFirstInstance` within a block.
+    UniformBlocks {
+        buffer: gl::GLuint,
+        items_per_block: usize,
+        item_size: usize,
+    },
+    /// The attributes are pointed at the first instance of each draw.
+    Attributes {
+        buffer: gl::GLuint,
+        vecs_per_item: usize,
+    },
+}
+
+/// Per-instance data, uploaded each frame through one of the backends.
+/// Instance data is a whole number of vec4s of floats per item.
+pub struct InstanceBuffer {
+    backend: InstanceBackend,
+    texture: Option<VertexDataTexture>,
+    buffer: gl::GLuint,
+    buffer_texture: gl::GLuint,
+    max_item_size: usize,
+    // Bytes allocated in `buffer` so far.
+    capacity: usize,
+}
+
+impl InstanceBuffer {
+    /// Items uploaded to the buffer can be at most `max_item_size` bytes,
+    /// which is checked against the backend's limits here.
+    pub fn new(backend: InstanceBackend, max_item_size: usize) -> Result<InstanceBuffer, String> {
+        if !backend.is_supported() {
+            return Err(format!("{} instance data is not supported by this GL implementation",
+                               backend.name()));
+        }
+        if max_item_size == 0 || !max_item_size.is_multiple_of(16) {
+            return Err(format!("instance items must be a whole number of vec4s, not {} bytes",
+                               max_item_size));
+        }
+        let max_vecs = match backend {
+            InstanceBackend::UniformBuffer => MAX_INSTANCE_UNIFORM_VECS,
+            InstanceBackend::Attributes => MAX_INSTANCE_ATTRIBUTES,
+            _ => usize::MAX,
+        };
+        if max_item_size / 16 > max_vecs {
+            return Err(format!("{} instance data holds at most {} vec4s per item, not {}",
+                               backend.name(),
+                               max_vecs,
+                               max_item_size / 16));
+        }
+
+        let mut buffer = InstanceBuffer {
+            backend,
+            texture: None,
+            buffer: 0,
+            buffer_texture: 0,
+            max_item_size,
+            capacity: 0,
+        };
+        match backend {
+            InstanceBackend::Texture => {
+                buffer.texture = Some(VertexDataTexture::new(VertexTextureFormat::F32));
+            }
+            InstanceBackend::TextureBuffer => {
+                buffer.buffer = gl::gen_buffers(1)[0];
+                buffer.buffer_texture = gl::gen_textures(1)[0];
+                gl::bind_buffer(gl::TEXTURE_BUFFER, buffer.buffer);
+                gl::bind_texture(gl::TEXTURE_BUFFER, buffer.buffer_texture);
+                unsafe {
+                    gl::TexBuffer(gl::TEXTURE_BUFFER, gl::RGBA32F, buffer.buffer);
+                }
+                gl::bind_texture(gl::TEXTURE_BUFFER, 0);
+                gl::bind_buffer(gl::TEXTURE_BUFFER, 0);
+            }
+            _ => {
+                buffer.buffer = gl::gen_buffers(1)[0];
+            }
+        }
+        Ok(buffer)
+    }
+
+    /// The texture that the backend's shaders sample as `sInstances`, if any.
+    pub fn texture_binding(&self) -> Option<TextureBinding> {
+        match self.backend {
+            InstanceBackend::Texture => self.texture.as_ref().map(|texture| texture.binding()),
+            InstanceBackend::TextureBuffer => Some(TextureBinding {
+                target: gl::TEXTURE_BUFFER,
+                id: self.buffer_texture,
+                kind: SamplerKind::Float,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Upload `data` and bind it where the backend's shaders read it from,
+    /// apart from `texture_binding`, which is bound by sampler name.
+    /// Returns where draws find the instances.
+    pub fn upload<T>(&mut self, data: &[T], uploader: &mut Uploader) -> InstanceLayout {
+        let item_size = mem::size_of::<T>();
+        debug_assert!(item_size.is_multiple_of(16) && item_size <= self.max_item_size);
+        let vecs_per_item = item_size / 16;
+
+        let bytes = unsafe {
+            slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data))
+        };
+
+        match self.backend {
+            InstanceBackend::Texture => {
+                self.texture.as_mut().unwrap().update(data, uploader);
+                InstanceLayout::Indexed
+            }
+            InstanceBackend::UniformBuffer => {
+                self.upload_buffer(gl::UNIFORM_BUFFER, bytes, uploader);
+                InstanceLayout::UniformBlocks {
+                    buffer: self.buffer,
+                    items_per_block: uniform_block_items(item_size),
+                    item_size,
+                }
+            }
+            InstanceBackend::TextureBuffer => {
+                self.upload_buffer(gl::TEXTURE_BUFFER, bytes, uploader);
+                InstanceLayout::Indexed
+            }
+            InstanceBackend::StorageBuffer => {
+                self.upload_buffer(SHADER_STORAGE_BUFFER, bytes, uploader);
+                // Storage blocks are bound to point zero when a program links.
+                gl::bind_buffer_base(SHADER_STORAGE_BUFFER, 0, self.buffer);
+                InstanceLayout::Indexed
+            }
+            InstanceBackend::Attributes => {
+                self.upload_buffer(gl::ARRAY_BUFFER, bytes, uploader);
+                InstanceLayout::Attributes {
+                    buffer: self.buffer,
+                    vecs_per_item,
+                }
+            }
+        }
+    }
+
+    // Grow the buffer geometrically, so that steady state uploads don't
+    // reallocate it.
+    fn upload_buffer(&mut self, target: gl::GLenum, bytes: &[u8], uploader: &mut Uploader) {
+        gl::bind_buffer(target, self.buffer);
+        if bytes.len() > self.capacity {
+            self.capacity = bytes.len().next_power_of_two().max(self.capacity * 2);
+            unsafe {
+                gl::BufferData(target, self.capacity as gl::GLsizeiptr, ptr::null(), gl::STREAM_DRAW);
+            }
+        }
+        uploader.upload_buffer(target, bytes);
+    }
+}
+
+impl Drop for InstanceBuffer {
+    fn drop(&mut self) {
+        if self.buffer != 0 {
+            gl::delete_buffers(&[self.buffer]);
+        }
+        if self.buffer_texture != 0 {
+            gl::delete_textures(&[self.buffer_texture]);
+        }
+    }
+}
+
+// The most items that fit in a uniform block, rounded down so that each
+// block starts at a valid buffer offset.
+fn uniform_block_items(item_size: usize) -> usize {
+    let alignment = cmp::max(gl::get_integer_v(gl::UNIFORM_BUFFER_OFFSET_ALIGNMENT), 1) as usize;
+    let mut items = MAX_INSTANCE_UNIFORM_VECS * 16 / item_size;
+    while items > 1 && !(items * item_size).is_multiple_of(alignment) {
+        items -= 1;
+    }
+    items
+}
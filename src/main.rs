@@ -1,103 +1,99 @@
 mod apps;
 mod app;
+mod atlas;
+mod backend;
+mod compare;
 mod gfx;
+mod gpu_profiler;
+mod input;
+mod instances;
+mod pipeline;
+mod json;
+mod options;
+mod preprocessor;
+mod reftest;
+mod reflection;
+mod registry;
+mod render_target;
+mod report;
+mod runner;
+mod shader_cache;
+mod shader_error;
+mod stats;
+mod suite;
+mod texture;
 mod types;
+mod upload;
 mod util;
+mod watcher;
 
 extern crate glutin;
 extern crate gleam;
+extern crate image;
 extern crate time;
-//extern crate inotify;
 
-use app::{App, AppKind};
+use backend::Backend;
 use gfx::GfxContext;
-use gleam::gl;
-use std::env;
-use time::precise_time_ns;
+use options::Mode;
+use registry::AppRegistry;
+use report::{Environment, Report};
+use std::process;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let options = options::parse_args();
 
-    let app_name = &args[1];
-    let app_window = glutin::WindowBuilder::new().with_dimensions(1920, 1080)
-                                                 .build()
-                                                 .unwrap();
-    app_window.set_title(app_name);
-    let _ = unsafe { app_window.make_current() };
-    gl::load_with(|s| app_window.get_proc_address(s) as *const _);
+    let mut registry = AppRegistry::new();
+    apps::register_all(&mut registry);
 
-    let mut gfx = GfxContext::new(app_window.create_window_proxy());
+    match options.mode {
+        Mode::List => {
+            registry.print_list();
+            return;
+        }
+        Mode::Suite => {
+            process::exit(suite::run_suite(&registry, &options));
+        }
+        Mode::Compare => {
+            process::exit(compare::run_compare(&options));
+        }
+        Mode::Reftest => {
+            process::exit(reftest::run_reftest(&registry, &options));
+        }
+        Mode::Run => {}
+    }
+
+    let spec = registry.resolve(&options.app_args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
 
-    let mut app = match app_name.as_str() {
-        "test" => Box::new(apps::test::Test::new(&mut gfx)) as Box<App>,
-        "null" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 0, 0)) as Box<App>,
-        "clear1" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 1, 0)) as Box<App>,
-        "clear2" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 2, 0)) as Box<App>,
-        "clear4" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 4, 0)) as Box<App>,
-        "clear8" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 8, 0)) as Box<App>,
-        "quad1" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 0, 1)) as Box<App>,
-        "quad2" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 0, 2)) as Box<App>,
-        "quad4" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 0, 4)) as Box<App>,
-        "quad8" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 0, 8)) as Box<App>,
-        "clear1_quad1" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 1, 1)) as Box<App>,
-        "clear1_quad2" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 1, 2)) as Box<App>,
-        "clear1_quad3" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 1, 3)) as Box<App>,
-        "clear1_quad4" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 1, 4)) as Box<App>,
-        "clear1_quad5" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 1, 5)) as Box<App>,
-        "clear1_quad6" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 1, 6)) as Box<App>,
-        "clear1_quad7" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 1, 7)) as Box<App>,
-        "clear1_quad8" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 1, 8)) as Box<App>,
-        "clear1_quad9" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 1, 9)) as Box<App>,
-        "clear1_quad10" => Box::new(apps::quad_bench::QuadBench::new(&mut gfx, 1, 10)) as Box<App>,
-        _ => panic!("unknown app name"),
+    let backend = if options.headless {
+        Backend::new_headless(options.width, options.height)
+    } else {
+        Backend::new_window(&spec.name, options.width, options.height)
     };
+    let backend = backend.unwrap_or_else(|e| {
+        eprintln!("Failed to create GL context: {}", e);
+        process::exit(1);
+    });
 
-    let start_time = precise_time_ns();
-    let mut frame_count = 0;
+    let mut gfx = GfxContext::new(backend.create_window_proxy(), options.upload);
 
-    loop {
-        match app.kind() {
-            AppKind::Test => {
-                let event = app_window.wait_events().next().unwrap();
-                let event = match event {
-                    glutin::Event::Closed => break,
-                    glutin::Event::KeyboardInput(state, scan_code, _) => {
-                        match state {
-                            glutin::ElementState::Pressed => {
-                                match scan_code {
-                                    9 => break,
-                                    _ => None
-                                }
-                            }
-                            _ => None,
-                        }
-                    }
-                    _ => None,
-                };
-                if let Some(event) = event {
-                    app.on_event(event);
-                }
-            }
-            AppKind::Benchmark => {
-                if frame_count == 500 {
-                    break;
-                }
+    let record = runner::run_app(&backend, &mut gfx, &spec, &options).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    let environment = Environment::query();
+    match options.report {
+        Some(format) => {
+            let mut report = Report::new(environment);
+            report.records.push(record);
+            if let Err(e) = report.write(format, options.output.as_deref()) {
+                eprintln!("Failed to write report: {}", e);
+                process::exit(1);
             }
         }
-
-        let (width, height) = app_window.get_inner_size().unwrap();
-        gfx.begin_frame(width, height);
-        app.draw(&mut gfx, width, height);
-        gfx.end_frame();
-
-        frame_count += 1;
-
-        let _ = app_window.swap_buffers();
+        None => runner::print_record(&record, &environment),
     }
-
-    let end_time = precise_time_ns();
-    let ms = (end_time - start_time) as f64 / 1000000.0;
-    println!("{} frames={} total={}ms avg={}ms", app_name, frame_count, ms, ms / frame_count as f64);
-
-    app.deinit(&mut gfx);
 }
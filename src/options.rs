@@ -0,0 +1,169 @@
+use compare::Metric;
+use report::ReportFormat;
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use upload::UploadMethod;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mode {
+    Run,            // Run a single app.
+    List,           // List registered apps.
+    Suite,          // Run a list of apps and produce one report.
+    Compare,        // Compare a report against a baseline report.
+    Reftest,        // Compare an app's output against a reference image.
+}
+
+#[derive(Clone)]
+pub struct Options {
+    pub mode: Mode,
+    pub app_args: Vec<String>,
+    pub headless: bool,
+    pub width: u32,
+    pub height: u32,
+    pub frames: Option<usize>,
+    pub warmup: usize,
+    pub report: Option<ReportFormat>,
+    pub output: Option<PathBuf>,
+    pub isolate: bool,
+    pub timeout: Option<u64>,
+    pub metric: Metric,
+    pub threshold: f64,
+    pub alpha: f64,
+    pub screenshot: Option<PathBuf>,
+    pub screenshot_frame: Option<usize>,
+    pub fuzz: u8,
+    pub max_differing: usize,
+    pub diff: Option<PathBuf>,
+    pub upload: UploadMethod,
+    /// Test apps in a window wait for input between frames.
+    pub interactive: bool,
+}
+
+pub fn usage() -> ! {
+    eprintln!("usage: gl_sandpit [options] <app> [param=value ...]");
+    eprintln!("       gl_sandpit [options] suite [--isolate] [--timeout SECS] <app|glob|@file> ...");
+    eprintln!("       gl_sandpit compare [--metric M] [--threshold PCT] [--alpha P] <baseline.json> <new.json>");
+    eprintln!("       gl_sandpit reftest [--fuzz N] [--max-diff N] [--diff FILE] <reference.png> <app> [param=value ...]");
+    eprintln!("       gl_sandpit --list");
+    eprintln!();
+    eprintln!("options:");
+    eprintln!("    --headless          Render offscreen without a window");
+    eprintln!("    --size WxH          Window or offscreen framebuffer size (default 1920x1080)");
+    eprintln!("    --frames N          Number of measured frames");
+    eprintln!("    --warmup N          Number of benchmark frames to discard first (default 20)");
+    eprintln!("    --report json|csv   Write a machine readable report instead of text");
+    eprintln!("    --output FILE       Write the report to FILE instead of stdout");
+    eprintln!("    --isolate           Run each suite app in its own process");
+    eprintln!("    --timeout SECS      Kill isolated suite apps that run longer than this");
+    eprintln!("    --metric M          Frame time to compare: total, cpu, swap or gpu (default total)");
+    eprintln!("    --threshold PCT     Median slowdown that counts as a regression (default 5)");
+    eprintln!("    --alpha P           Significance level for comparisons (default 0.01)");
+    eprintln!("    --upload METHOD     Upload data with direct, pbo or persistent (default direct)");
+    eprintln!("    --no-input          Draw test apps in a window for a fixed number of frames");
+    eprintln!("                        instead of waiting for input (suites always do)");
+    eprintln!("    --screenshot FILE   Save the framebuffer as a PNG after the last frame,");
+    eprintln!("                        or when F12 is pressed in a test window");
+    eprintln!("    --screenshot-frame N  Save the screenshot after frame N instead");
+    eprintln!("    --fuzz N            Largest per-channel difference a reftest ignores (default 0)");
+    eprintln!("    --max-diff N        Number of differing pixels a reftest allows (default 0)");
+    eprintln!("    --diff FILE         Where a failing reftest writes its diff image");
+    eprintln!("                        (default <reference>-diff.png)");
+    eprintln!();
+    eprintln!("environment:");
+    eprintln!("    GL_SANDPIT_SHADER_CACHE  Directory for cached program binaries");
+    eprintln!("                             (default gl_sandpit_shaders in the temp dir)");
+    process::exit(1);
+}
+
+fn next_value<T, I>(args: &mut I) -> T where T: ::std::str::FromStr, I: Iterator<Item=String> {
+    let value = args.next().unwrap_or_else(|| usage());
+    value.parse().unwrap_or_else(|_| usage())
+}
+
+pub fn parse_args() -> Options {
+    let mut options = Options {
+        mode: Mode::Run,
+        app_args: Vec::new(),
+        headless: false,
+        width: 1920,
+        height: 1080,
+        frames: None,
+        warmup: 20,
+        report: None,
+        output: None,
+        isolate: false,
+        timeout: None,
+        metric: Metric::Total,
+        threshold: 5.0,
+        alpha: 0.01,
+        screenshot: None,
+        screenshot_frame: None,
+        fuzz: 0,
+        max_differing: 0,
+        diff: None,
+        upload: UploadMethod::Direct,
+        interactive: true,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--list" => options.mode = Mode::List,
+            "--headless" => options.headless = true,
+            "--size" => {
+                let size: String = next_value(&mut args);
+                let mut dims = size.split('x').map(|d| d.parse::<u32>());
+                match (dims.next(), dims.next(), dims.next()) {
+                    (Some(Ok(w)), Some(Ok(h)), None) if w > 0 && h > 0 => {
+                        options.width = w;
+                        options.height = h;
+                    }
+                    _ => usage(),
+                }
+            }
+            "--frames" => options.frames = Some(next_value(&mut args)),
+            "--warmup" => options.warmup = next_value(&mut args),
+            "--report" => {
+                let format: String = next_value(&mut args);
+                options.report = Some(ReportFormat::parse(&format).unwrap_or_else(|| usage()));
+            }
+            "--output" => options.output = Some(next_value(&mut args)),
+            "--isolate" => options.isolate = true,
+            "--timeout" => options.timeout = Some(next_value(&mut args)),
+            "--metric" => {
+                let metric: String = next_value(&mut args);
+                options.metric = Metric::parse(&metric).unwrap_or_else(|| usage());
+            }
+            "--threshold" => options.threshold = next_value(&mut args),
+            "--alpha" => options.alpha = next_value(&mut args),
+            "--upload" => {
+                let method: String = next_value(&mut args);
+                options.upload = UploadMethod::parse(&method).unwrap_or_else(|| usage());
+            }
+            "--screenshot" => options.screenshot = Some(next_value(&mut args)),
+            "--screenshot-frame" => options.screenshot_frame = Some(next_value(&mut args)),
+            "--fuzz" => options.fuzz = next_value(&mut args),
+            "--max-diff" => options.max_differing = next_value(&mut args),
+            "--diff" => options.diff = Some(next_value(&mut args)),
+            "--no-input" => options.interactive = false,
+            _ if arg.starts_with("--") => usage(),
+            "suite" if options.mode == Mode::Run && options.app_args.is_empty() => {
+                options.mode = Mode::Suite;
+            }
+            "compare" if options.mode == Mode::Run && options.app_args.is_empty() => {
+                options.mode = Mode::Compare;
+            }
+            "reftest" if options.mode == Mode::Run && options.app_args.is_empty() => {
+                options.mode = Mode::Reftest;
+            }
+            _ => options.app_args.push(arg),
+        }
+    }
+
+    if options.app_args.is_empty() && options.mode != Mode::List {
+        usage();
+    }
+
+    options
+}
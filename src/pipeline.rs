@@ -0,0 +1,278 @@
+use gleam::gl;
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BlendFactor {
+    Zero,
+    One,
+    SrcColor,
+    OneMinusSrcColor,
+    SrcAlpha,
+    OneMinusSrcAlpha,
+    DstColor,
+    OneMinusDstColor,
+    DstAlpha,
+    OneMinusDstAlpha,
+    ConstantColor,
+    OneMinusConstantColor,
+    /// Dual source factors read oFragBlend, which shaders declare by
+    /// defining DUAL_SOURCE_BLENDING.
+    Src1Color,
+    OneMinusSrc1Color,
+    Src1Alpha,
+    OneMinusSrc1Alpha,
+}
+
+impl BlendFactor {
+    fn to_gl(self) -> gl::GLenum {
+        match self {
+            BlendFactor::Zero => gl::ZERO,
+            BlendFactor::One => gl::ONE,
+            BlendFactor::SrcColor => gl::SRC_COLOR,
+            BlendFactor::OneMinusSrcColor => gl::ONE_MINUS_SRC_COLOR,
+            BlendFactor::SrcAlpha => gl::SRC_ALPHA,
+            BlendFactor::OneMinusSrcAlpha => gl::ONE_MINUS_SRC_ALPHA,
+            BlendFactor::DstColor => gl::DST_COLOR,
+            BlendFactor::OneMinusDstColor => gl::ONE_MINUS_DST_COLOR,
+            BlendFactor::DstAlpha => gl::DST_ALPHA,
+            BlendFactor::OneMinusDstAlpha => gl::ONE_MINUS_DST_ALPHA,
+            BlendFactor::ConstantColor => gl::CONSTANT_COLOR,
+            BlendFactor::OneMinusConstantColor => gl::ONE_MINUS_CONSTANT_COLOR,
+            BlendFactor::Src1Color => gl::SRC1_COLOR,
+            BlendFactor::OneMinusSrc1Color => gl::ONE_MINUS_SRC1_COLOR,
+            BlendFactor::Src1Alpha => gl::SRC1_ALPHA,
+            BlendFactor::OneMinusSrc1Alpha => gl::ONE_MINUS_SRC1_ALPHA,
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BlendOp {
+    Add,
+    Subtract,
+    ReverseSubtract,
+    Min,
+    Max,
+}
+
+impl BlendOp {
+    fn to_gl(self) -> gl::GLenum {
+        match self {
+            BlendOp::Add => gl::FUNC_ADD,
+            BlendOp::Subtract => gl::FUNC_SUBTRACT,
+            BlendOp::ReverseSubtract => gl::FUNC_REVERSE_SUBTRACT,
+            BlendOp::Min => gl::MIN,
+            BlendOp::Max => gl::MAX,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BlendState {
+    pub color_op: BlendOp,
+    pub src_color: BlendFactor,
+    pub dst_color: BlendFactor,
+    pub alpha_op: BlendOp,
+    pub src_alpha: BlendFactor,
+    pub dst_alpha: BlendFactor,
+    /// Used by the constant factors.
+    pub constant: [f32; 4],
+}
+
+impl BlendState {
+    pub fn new(op: BlendOp, src: BlendFactor, dst: BlendFactor) -> BlendState {
+        BlendState {
+            color_op: op,
+            src_color: src,
+            dst_color: dst,
+            alpha_op: op,
+            src_alpha: src,
+            dst_alpha: dst,
+            constant: [0.0; 4],
+        }
+    }
+
+    /// Source over, for colors with premultiplied alpha.
+    pub fn premultiplied_alpha() -> BlendState {
+        BlendState::new(BlendOp::Add, BlendFactor::One, BlendFactor::OneMinusSrcAlpha)
+    }
+
+    pub fn additive() -> BlendState {
+        BlendState::new(BlendOp::Add, BlendFactor::One, BlendFactor::One)
+    }
+
+    /// Per-channel coverage from oFragBlend, as used for subpixel text.
+    pub fn dual_source() -> BlendState {
+        BlendState::new(BlendOp::Add, BlendFactor::One, BlendFactor::OneMinusSrc1Color)
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CompareFunc {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl CompareFunc {
+    fn to_gl(self) -> gl::GLenum {
+        match self {
+            CompareFunc::Never => gl::NEVER,
+            CompareFunc::Less => gl::LESS,
+            CompareFunc::Equal => gl::EQUAL,
+            CompareFunc::LessEqual => gl::LEQUAL,
+            CompareFunc::Greater => gl::GREATER,
+            CompareFunc::NotEqual => gl::NOTEQUAL,
+            CompareFunc::GreaterEqual => gl::GEQUAL,
+            CompareFunc::Always => gl::ALWAYS,
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    Increment,
+    IncrementWrap,
+    Decrement,
+    DecrementWrap,
+    Invert,
+}
+
+impl StencilOp {
+    fn to_gl(self) -> gl::GLenum {
+        match self {
+            StencilOp::Keep => gl::KEEP,
+            StencilOp::Zero => gl::ZERO,
+            StencilOp::Replace => gl::REPLACE,
+            StencilOp::Increment => gl::INCR,
+            StencilOp::IncrementWrap => gl::INCR_WRAP,
+            StencilOp::Decrement => gl::DECR,
+            StencilOp::DecrementWrap => gl::DECR_WRAP,
+            StencilOp::Invert => gl::INVERT,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StencilState {
+    pub func: CompareFunc,
+    pub reference: i32,
+    pub read_mask: u32,
+    pub write_mask: u32,
+    pub fail: StencilOp,
+    pub depth_fail: StencilOp,
+    pub pass: StencilOp,
+}
+
+impl StencilState {
+    /// Passes everywhere and keeps the stored values, with every bit
+    /// writable, as clearing the stencil buffer needs.
+    pub fn write_all() -> StencilState {
+        StencilState {
+            func: CompareFunc::Always,
+            reference: 0,
+            read_mask: !0,
+            write_mask: !0,
+            fail: StencilOp::Keep,
+            depth_fail: StencilOp::Keep,
+            pass: StencilOp::Keep,
+        }
+    }
+}
+
+/// Fixed function state for a draw. Depth and stencil need a framebuffer
+/// with a depth-stencil buffer, which render targets don't have.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PipelineState {
+    /// None disables blending.
+    pub blend: Option<BlendState>,
+    /// None disables the depth test.
+    pub depth_test: Option<CompareFunc>,
+    pub depth_write: bool,
+    /// None disables the stencil test.
+    pub stencil: Option<StencilState>,
+    pub color_mask: [bool; 4],
+}
+
+impl Default for PipelineState {
+    /// Opaque drawing, with every test disabled.
+    fn default() -> PipelineState {
+        PipelineState {
+            blend: None,
+            depth_test: None,
+            depth_write: false,
+            stencil: None,
+            color_mask: [true; 4],
+        }
+    }
+}
+
+impl PipelineState {
+    pub fn blended(blend: BlendState) -> PipelineState {
+        PipelineState {
+            blend: Some(blend),
+            ..PipelineState::default()
+        }
+    }
+
+    /// Set the GL state that differs from `current`, or all of it if the
+    /// current state isn't known.
+    pub fn apply(&self, current: Option<&PipelineState>) {
+        if current.map(|c| c.blend) != Some(self.blend) {
+            match self.blend {
+                Some(blend) => {
+                    gl::enable(gl::BLEND);
+                    gl::blend_equation_separate(blend.color_op.to_gl(), blend.alpha_op.to_gl());
+                    gl::blend_func_separate(blend.src_color.to_gl(),
+                                            blend.dst_color.to_gl(),
+                                            blend.src_alpha.to_gl(),
+                                            blend.dst_alpha.to_gl());
+                    gl::blend_color(blend.constant[0], blend.constant[1], blend.constant[2], blend.constant[3]);
+                }
+                None => gl::disable(gl::BLEND),
+            }
+        }
+
+        if current.map(|c| c.depth_test) != Some(self.depth_test) {
+            match self.depth_test {
+                Some(func) => {
+                    gl::enable(gl::DEPTH_TEST);
+                    gl::depth_func(func.to_gl());
+                }
+                None => gl::disable(gl::DEPTH_TEST),
+            }
+        }
+
+        if current.map(|c| c.depth_write) != Some(self.depth_write) {
+            gl::depth_mask(self.depth_write);
+        }
+
+        if current.map(|c| c.stencil) != Some(self.stencil) {
+            match self.stencil {
+                Some(stencil) => {
+                    gl::enable(gl::STENCIL_TEST);
+                    gl::stencil_func(stencil.func.to_gl(), stencil.reference, stencil.read_mask);
+                    gl::stencil_mask(stencil.write_mask);
+                    gl::stencil_op(stencil.fail.to_gl(), stencil.depth_fail.to_gl(), stencil.pass.to_gl());
+                }
+                None => gl::disable(gl::STENCIL_TEST),
+            }
+        }
+
+        if current.map(|c| c.color_mask) != Some(self.color_mask) {
+            let mask = self.color_mask;
+            gl::color_mask(mask[0], mask[1], mask[2], mask[3]);
+        }
+    }
+}
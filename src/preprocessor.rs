@@ -0,0 +1,248 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// A shader file with its includes expanded.
+pub struct ShaderSource {
+    /// Source text, with `#line` directives mapping each line back to its
+    /// file. The source string number in a directive indexes `files`.
+    pub text: String,
+    /// Every file the source was built from, starting with the root file.
+    pub files: Vec<PathBuf>,
+}
+
+/// Expand `#include "file"` directives, relative to the including file.
+/// Files containing `#pragma once` are only expanded the first time they
+/// are included, which also ends any include cycle through them. Other
+/// include cycles are an error.
+///
+/// This runs before the GLSL preprocessor, so includes are expanded
+/// regardless of any surrounding `#if` blocks: an include guarded by a
+/// define that isn't set is still read, must still exist, and still counts
+/// as a dependency for shader reloading.
+pub fn preprocess(path: &Path) -> Result<ShaderSource, String> {
+    let mut preprocessor = Preprocessor {
+        text: String::new(),
+        files: Vec::new(),
+        once: Vec::new(),
+        stack: Vec::new(),
+    };
+    preprocessor.expand(path)?;
+    Ok(ShaderSource {
+        text: preprocessor.text,
+        files: preprocessor.files,
+    })
+}
+
+struct Preprocessor {
+    text: String,
+    files: Vec<PathBuf>,
+    once: Vec<PathBuf>,
+    stack: Vec<PathBuf>,
+}
+
+fn parse_include(line: &str) -> Option<Result<&str, ()>> {
+    let directive = line.trim_start().strip_prefix('#')?.trim_start();
+    let argument = directive.strip_prefix("include")?.trim();
+    Some(argument.strip_prefix('"')
+                 .and_then(|a| a.strip_suffix('"'))
+                 .filter(|a| !a.is_empty())
+                 .ok_or(()))
+}
+
+fn is_pragma_once(line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    words.next() == Some("#pragma") && words.next() == Some("once") && words.next().is_none()
+}
+
+impl Preprocessor {
+    fn expand(&mut self, path: &Path) -> Result<(), String> {
+        if self.once.iter().any(|p| p == path) {
+            return Ok(());
+        }
+        if self.stack.iter().any(|p| p == path) {
+            let cycle: Vec<String> = self.stack.iter()
+                                               .chain(Some(&path.to_path_buf()))
+                                               .map(|p| p.display().to_string())
+                                               .collect();
+            return Err(format!("include cycle: {}", cycle.join(" -> ")));
+        }
+
+        let mut source = String::new();
+        File::open(path).and_then(|mut f| f.read_to_string(&mut source))
+                        .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+
+        let index = match self.files.iter().position(|p| p == path) {
+            Some(index) => index,
+            None => {
+                self.files.push(path.to_path_buf());
+                self.files.len() - 1
+            }
+        };
+
+        // Before GLSL 3.30, the line after `#line N` is numbered N + 1.
+        self.stack.push(path.to_path_buf());
+        self.text.push_str(&format!("#line 0 {}\n", index));
+
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i + 1;
+
+            if is_pragma_once(line) {
+                self.once.push(path.to_path_buf());
+                self.text.push('\n');
+                continue;
+            }
+
+            match parse_include(line) {
+                Some(Ok(name)) => {
+                    let include_path = path.parent().unwrap_or(Path::new("")).join(name);
+                    self.expand(&include_path).map_err(|e| {
+                        format!("{}\n  included from {}:{}", e, path.display(), line_number)
+                    })?;
+                    self.text.push_str(&format!("#line {} {}\n", line_number, index));
+                }
+                Some(Err(())) => {
+                    return Err(format!("{}:{}: malformed #include", path.display(), line_number));
+                }
+                None => {
+                    self.text.push_str(line);
+                    self.text.push('\n');
+                }
+            }
+        }
+
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::preprocess;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    /// A directory of shader files that is removed when dropped.
+    struct TestDir {
+        path: PathBuf,
+    }
+
+    impl TestDir {
+        fn new(name: &str, files: &[(&str, &str)]) -> TestDir {
+            let path = env::temp_dir().join(format!("gl_sandpit_preprocessor_{}_{}", process::id(), name));
+            let _ = fs::remove_dir_all(&path);
+            for &(file, text) in files {
+                let file = path.join(file);
+                fs::create_dir_all(file.parent().unwrap()).unwrap();
+                fs::write(file, text).unwrap();
+            }
+            TestDir {
+                path,
+            }
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    #[test]
+    fn nested_includes_and_line_numbers() {
+        let dir = TestDir::new("nested", &[
+            ("main.glsl", "// main\n#include \"lib/a.glsl\"\nvoid main() {}\n"),
+            ("lib/a.glsl", "#include \"b.glsl\"\nfloat a;\n"),
+            ("lib/b.glsl", "float b;\n"),
+        ]);
+        let source = preprocess(&dir.path.join("main.glsl")).unwrap();
+
+        assert_eq!(source.files, vec![dir.path.join("main.glsl"),
+                                      dir.path.join("lib/a.glsl"),
+                                      dir.path.join("lib/b.glsl")]);
+        // `#line N` numbers the following line N + 1, so each file starts
+        // at 0 and resumes after an include at the include's line number.
+        assert_eq!(source.text, "#line 0 0\n\
+                                 // main\n\
+                                 #line 0 1\n\
+                                 #line 0 2\n\
+                                 float b;\n\
+                                 #line 1 1\n\
+                                 float a;\n\
+                                 #line 2 0\n\
+                                 void main() {}\n");
+    }
+
+    #[test]
+    fn pragma_once_files_are_expanded_once() {
+        let dir = TestDir::new("once", &[
+            ("main.glsl", "#include \"shared.glsl\"\n#include \"other.glsl\"\n#include \"shared.glsl\"\n"),
+            ("other.glsl", "#include \"shared.glsl\"\nfloat other;\n"),
+            ("shared.glsl", "#pragma once\nfloat shared;\n"),
+        ]);
+        let source = preprocess(&dir.path.join("main.glsl")).unwrap();
+        assert_eq!(source.text.matches("float shared;").count(), 1);
+        assert_eq!(source.text.matches("float other;").count(), 1);
+        assert!(!source.text.contains("#pragma once"));
+        assert_eq!(source.files.len(), 3);
+    }
+
+    #[test]
+    fn files_without_pragma_once_repeat() {
+        let dir = TestDir::new("repeat", &[
+            ("main.glsl", "#include \"part.glsl\"\n#include \"part.glsl\"\n"),
+            ("part.glsl", "float part;\n"),
+        ]);
+        let source = preprocess(&dir.path.join("main.glsl")).unwrap();
+        assert_eq!(source.text.matches("float part;").count(), 2);
+        assert_eq!(source.files.len(), 2);
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        let dir = TestDir::new("cycle", &[
+            ("main.glsl", "#include \"a.glsl\"\n"),
+            ("a.glsl", "#include \"b.glsl\"\n"),
+            ("b.glsl", "#include \"a.glsl\"\n"),
+            ("guarded.glsl", "#pragma once\n#include \"c.glsl\"\nfloat guarded;\n"),
+            ("c.glsl", "#include \"guarded.glsl\"\n"),
+        ]);
+        let error = preprocess(&dir.path.join("main.glsl")).err().unwrap();
+        let a = dir.path.join("a.glsl").display().to_string();
+        let b = dir.path.join("b.glsl").display().to_string();
+        assert!(error.starts_with(&format!("include cycle: {} -> {} -> {} -> {}",
+                                           dir.path.join("main.glsl").display(), a, b, a)),
+                "{}", error);
+        assert!(error.contains(&format!("included from {}:1", dir.path.join("main.glsl").display())),
+                "{}", error);
+
+        // Like in C, a file with `#pragma once` above the include ends the cycle.
+        let source = preprocess(&dir.path.join("guarded.glsl")).unwrap();
+        assert_eq!(source.text.matches("float guarded;").count(), 1);
+    }
+
+    #[test]
+    fn missing_files_are_errors() {
+        let dir = TestDir::new("missing", &[
+            ("main.glsl", "float a;\n#ifdef NEVER_DEFINED\n#include \"missing.glsl\"\n#endif\n"),
+        ]);
+        let error = preprocess(&dir.path.join("main.glsl")).err().unwrap();
+        assert!(error.contains(&format!("unable to read {}", dir.path.join("missing.glsl").display())),
+                "{}", error);
+        assert!(error.contains(&format!("included from {}:3", dir.path.join("main.glsl").display())),
+                "{}", error);
+
+        assert!(preprocess(&dir.path.join("absent.glsl")).is_err());
+    }
+
+    #[test]
+    fn malformed_includes_are_errors() {
+        let dir = TestDir::new("malformed", &[
+            ("main.glsl", "\n#include <other.glsl>\n"),
+        ]);
+        let error = preprocess(&dir.path.join("main.glsl")).err().unwrap();
+        assert!(error.ends_with(":2: malformed #include"), "{}", error);
+    }
+}
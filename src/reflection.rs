@@ -0,0 +1,245 @@
+use gleam::gl;
+use types::ColorF;
+
+/// An active uniform of a linked program.
+#[derive(Debug, Clone)]
+pub struct ActiveVariable {
+    pub name: String,
+    pub ty: gl::GLenum,
+    pub location: gl::GLint,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProgramReflection {
+    pub uniforms: Vec<ActiveVariable>,
+}
+
+impl ProgramReflection {
+    pub fn query(program: gl::GLuint) -> ProgramReflection {
+        let uniform_count = gl::get_program_iv(program, gl::ACTIVE_UNIFORMS);
+        let uniforms = (0..uniform_count as gl::GLuint).map(|i| {
+            let (_, ty, name) = gl::get_active_uniform(program, i);
+            let location = gl::get_uniform_location(program, &name);
+            ActiveVariable {
+                name: strip_array_suffix(&name).to_owned(),
+                ty,
+                location,
+            }
+        }).collect();
+
+        ProgramReflection {
+            uniforms,
+        }
+    }
+
+    /// Uniforms in named uniform blocks have no location and aren't listed.
+    pub fn uniform(&self, name: &str) -> Option<&ActiveVariable> {
+        self.uniforms.iter().find(|u| u.name == name && u.location != -1)
+    }
+}
+
+// Arrays are reported as "name[0]".
+fn strip_array_suffix(name: &str) -> &str {
+    name.strip_suffix("[0]").unwrap_or(name)
+}
+
+pub fn type_name(ty: gl::GLenum) -> &'static str {
+    match ty {
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::INT => "int",
+        gl::INT_VEC2 => "ivec2",
+        gl::INT_VEC3 => "ivec3",
+        gl::INT_VEC4 => "ivec4",
+        gl::UNSIGNED_INT => "uint",
+        gl::BOOL => "bool",
+        gl::FLOAT_MAT2 => "mat2",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::SAMPLER_2D => "sampler2D",
+        gl::INT_SAMPLER_2D => "isampler2D",
+        gl::UNSIGNED_INT_SAMPLER_2D => "usampler2D",
+        gl::SAMPLER_BUFFER => "samplerBuffer",
+        gl::INT_SAMPLER_BUFFER => "isamplerBuffer",
+        gl::UNSIGNED_INT_SAMPLER_BUFFER => "usamplerBuffer",
+        _ => "unknown",
+    }
+}
+
+/// The kind of values a sampler returns. Textures must have a matching
+/// format: normalized or float formats for `Float`, and integer formats
+/// for `Int` and `UInt`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SamplerKind {
+    Float,
+    Int,
+    UInt,
+}
+
+const SAMPLER_TYPES: [gl::GLenum; 6] = [
+    gl::SAMPLER_2D,
+    gl::INT_SAMPLER_2D,
+    gl::UNSIGNED_INT_SAMPLER_2D,
+    gl::SAMPLER_BUFFER,
+    gl::INT_SAMPLER_BUFFER,
+    gl::UNSIGNED_INT_SAMPLER_BUFFER,
+];
+
+/// The kind and texture target of a sampler uniform type, or None if the
+/// type isn't a supported sampler.
+pub fn sampler_type(ty: gl::GLenum) -> Option<(SamplerKind, gl::GLenum)> {
+    match ty {
+        gl::SAMPLER_2D => Some((SamplerKind::Float, gl::TEXTURE_2D)),
+        gl::INT_SAMPLER_2D => Some((SamplerKind::Int, gl::TEXTURE_2D)),
+        gl::UNSIGNED_INT_SAMPLER_2D => Some((SamplerKind::UInt, gl::TEXTURE_2D)),
+        gl::SAMPLER_BUFFER => Some((SamplerKind::Float, gl::TEXTURE_BUFFER)),
+        gl::INT_SAMPLER_BUFFER => Some((SamplerKind::Int, gl::TEXTURE_BUFFER)),
+        gl::UNSIGNED_INT_SAMPLER_BUFFER => Some((SamplerKind::UInt, gl::TEXTURE_BUFFER)),
+        _ => None,
+    }
+}
+
+/// The GLSL name of the sampler type that reads textures of `kind` and `target`.
+pub fn sampler_type_name(kind: SamplerKind, target: gl::GLenum) -> &'static str {
+    SAMPLER_TYPES.iter()
+                 .find(|&&ty| sampler_type(ty) == Some((kind, target)))
+                 .map_or("unknown", |&ty| type_name(ty))
+}
+
+/// A value that can be assigned to a uniform with `GfxContext::set_uniform`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum UniformValue {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    Int(i32),
+    IVec2([i32; 2]),
+    IVec3([i32; 3]),
+    IVec4([i32; 4]),
+    UInt(u32),
+    Bool(bool),
+    Mat4([f32; 16]),
+}
+
+impl UniformValue {
+    fn gl_type(&self) -> gl::GLenum {
+        match *self {
+            UniformValue::Float(..) => gl::FLOAT,
+            UniformValue::Vec2(..) => gl::FLOAT_VEC2,
+            UniformValue::Vec3(..) => gl::FLOAT_VEC3,
+            UniformValue::Vec4(..) => gl::FLOAT_VEC4,
+            UniformValue::Int(..) => gl::INT,
+            UniformValue::IVec2(..) => gl::INT_VEC2,
+            UniformValue::IVec3(..) => gl::INT_VEC3,
+            UniformValue::IVec4(..) => gl::INT_VEC4,
+            UniformValue::UInt(..) => gl::UNSIGNED_INT,
+            UniformValue::Bool(..) => gl::BOOL,
+            UniformValue::Mat4(..) => gl::FLOAT_MAT4,
+        }
+    }
+
+    /// Whether this value can be assigned to a uniform of type `ty`.
+    /// Samplers are set with texture unit numbers.
+    pub fn matches(&self, ty: gl::GLenum) -> bool {
+        match *self {
+            UniformValue::Int(..) if sampler_type(ty).is_some() => true,
+            _ => self.gl_type() == ty,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        type_name(self.gl_type())
+    }
+
+    /// Assign the value to a uniform of the bound program.
+    pub fn apply(&self, location: gl::GLint) {
+        match *self {
+            UniformValue::Float(v) => gl::uniform_1f(location, v),
+            UniformValue::Vec2(v) => gl::uniform_2f(location, v[0], v[1]),
+            UniformValue::Vec3(v) => gl::uniform_3f(location, v[0], v[1], v[2]),
+            UniformValue::Vec4(v) => gl::uniform_4f(location, v[0], v[1], v[2], v[3]),
+            UniformValue::Int(v) => gl::uniform_1i(location, v),
+            UniformValue::IVec2(v) => gl::uniform_2i(location, v[0], v[1]),
+            UniformValue::IVec3(v) => gl::uniform_3i(location, v[0], v[1], v[2]),
+            UniformValue::IVec4(v) => gl::uniform_4i(location, v[0], v[1], v[2], v[3]),
+            UniformValue::UInt(v) => gl::uniform_1ui(location, v),
+            UniformValue::Bool(v) => gl::uniform_1i(location, v as gl::GLint),
+            UniformValue::Mat4(ref v) => gl::uniform_matrix_4fv(location, false, v),
+        }
+    }
+}
+
+impl From<f32> for UniformValue {
+    fn from(v: f32) -> UniformValue {
+        UniformValue::Float(v)
+    }
+}
+
+impl From<[f32; 2]> for UniformValue {
+    fn from(v: [f32; 2]) -> UniformValue {
+        UniformValue::Vec2(v)
+    }
+}
+
+impl From<[f32; 3]> for UniformValue {
+    fn from(v: [f32; 3]) -> UniformValue {
+        UniformValue::Vec3(v)
+    }
+}
+
+impl From<[f32; 4]> for UniformValue {
+    fn from(v: [f32; 4]) -> UniformValue {
+        UniformValue::Vec4(v)
+    }
+}
+
+impl From<ColorF> for UniformValue {
+    fn from(c: ColorF) -> UniformValue {
+        UniformValue::Vec4([c.r, c.g, c.b, c.a])
+    }
+}
+
+impl From<i32> for UniformValue {
+    fn from(v: i32) -> UniformValue {
+        UniformValue::Int(v)
+    }
+}
+
+impl From<[i32; 2]> for UniformValue {
+    fn from(v: [i32; 2]) -> UniformValue {
+        UniformValue::IVec2(v)
+    }
+}
+
+impl From<[i32; 3]> for UniformValue {
+    fn from(v: [i32; 3]) -> UniformValue {
+        UniformValue::IVec3(v)
+    }
+}
+
+impl From<[i32; 4]> for UniformValue {
+    fn from(v: [i32; 4]) -> UniformValue {
+        UniformValue::IVec4(v)
+    }
+}
+
+impl From<u32> for UniformValue {
+    fn from(v: u32) -> UniformValue {
+        UniformValue::UInt(v)
+    }
+}
+
+impl From<bool> for UniformValue {
+    fn from(v: bool) -> UniformValue {
+        UniformValue::Bool(v)
+    }
+}
+
+impl From<[f32; 16]> for UniformValue {
+    fn from(v: [f32; 16]) -> UniformValue {
+        UniformValue::Mat4(v)
+    }
+}
@@ -0,0 +1,141 @@
+use backend::Backend;
+use gfx::GfxContext;
+use options::Options;
+use registry::AppRegistry;
+use std::path::{Path, PathBuf};
+use util;
+
+/// Per-pixel comparison of two equally sized RGBA8 images.
+struct ImageDiff {
+    differing: usize,
+    max_difference: u8,
+    diff_pixels: Vec<u8>,
+}
+
+/// A pixel differs when any channel differs by more than `fuzz`. The diff
+/// image shows differing pixels in red over a dimmed copy of the reference.
+fn compare_images(reference: &[u8], actual: &[u8], fuzz: u8) -> ImageDiff {
+    let mut differing = 0;
+    let mut max_difference = 0;
+    let mut diff_pixels = Vec::with_capacity(reference.len());
+
+    for (r, a) in reference.chunks(4).zip(actual.chunks(4)) {
+        let difference = r.iter().zip(a).map(|(&r, &a)| (r as i32 - a as i32).unsigned_abs() as u8)
+                                         .max()
+                                         .unwrap_or(0);
+        max_difference = max_difference.max(difference);
+        if difference > fuzz {
+            differing += 1;
+            diff_pixels.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let luma = (r[0] as u32 * 3 + r[1] as u32 * 6 + r[2] as u32) / 10;
+            let dimmed = (luma / 4) as u8;
+            diff_pixels.extend_from_slice(&[dimmed, dimmed, dimmed, 255]);
+        }
+    }
+
+    ImageDiff {
+        differing,
+        max_difference,
+        diff_pixels,
+    }
+}
+
+fn default_diff_path(reference: &Path) -> PathBuf {
+    let stem = reference.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    reference.with_file_name(format!("{}-diff.png", stem))
+}
+
+/// Render an app for a fixed number of frames at the size of the reference
+/// image, and compare the final frame against it. Returns the process exit
+/// code: 0 on a match, 1 on a mismatch and 2 on errors.
+pub fn run_reftest(registry: &AppRegistry, options: &Options) -> i32 {
+    if options.app_args.len() < 2 {
+        eprintln!("reftest expects a reference image and an app");
+        return 2;
+    }
+
+    let reference_path = Path::new(&options.app_args[0]);
+    let (width, height, reference) = match util::load_image(reference_path) {
+        Ok(image) => image,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+
+    let spec = match registry.resolve(&options.app_args[1..]) {
+        Ok(spec) => spec,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+
+    let backend = if options.headless {
+        Backend::new_headless(width, height)
+    } else {
+        Backend::new_window(&spec.name, width, height)
+    };
+    let backend = match backend {
+        Ok(backend) => backend,
+        Err(e) => {
+            eprintln!("Failed to create GL context: {}", e);
+            return 2;
+        }
+    };
+
+    // Windows may be resized by the window manager, which would make
+    // the read back meaningless.
+    if backend.size() != (width, height) {
+        eprintln!("Framebuffer is {:?}, but the reference is {}x{}", backend.size(), width, height);
+        return 2;
+    }
+
+    let mut gfx = GfxContext::new(backend.create_window_proxy(), options.upload);
+    let mut app = match spec.create(&mut gfx) {
+        Ok(app) => app,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+
+    let frames = options.frames.unwrap_or(1).max(1);
+    let mut actual = Vec::new();
+    for frame in 0..frames {
+        if let Err(e) = gfx.begin_frame(width, height) {
+            eprintln!("{}", e);
+            return 2;
+        }
+        app.draw(&mut gfx, width, height);
+        gfx.end_frame();
+        if frame + 1 == frames {
+            actual = gfx.read_pixels(width, height);
+        }
+        backend.swap_buffers();
+    }
+    app.deinit(&mut gfx);
+
+    let diff = compare_images(&reference, &actual, options.fuzz);
+    let passed = diff.differing <= options.max_differing;
+    println!("{} {}: {} of {} pixels differ (max channel difference {}, fuzz {}, allowed {})",
+             if passed { "PASS" } else { "FAIL" },
+             spec.name,
+             diff.differing,
+             width as usize * height as usize,
+             diff.max_difference,
+             options.fuzz,
+             options.max_differing);
+
+    if passed {
+        return 0;
+    }
+
+    let diff_path = options.diff.clone().unwrap_or_else(|| default_diff_path(reference_path));
+    match util::save_png(&diff_path, &diff.diff_pixels, width, height) {
+        Ok(()) => eprintln!("Wrote diff image to {}", diff_path.display()),
+        Err(e) => eprintln!("Failed to write diff image: {}", e),
+    }
+    1
+}
@@ -0,0 +1,452 @@
+use app::App;
+use gfx::GfxContext;
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ParamKind {
+    UInt,
+    Float,
+    /// One of a fixed set of names.
+    Choice(&'static [&'static str]),
+}
+
+impl ParamKind {
+    fn describe(&self) -> String {
+        match *self {
+            ParamKind::UInt => "uint".to_owned(),
+            ParamKind::Float => "float".to_owned(),
+            ParamKind::Choice(choices) => choices.join("|"),
+        }
+    }
+
+    fn parse(&self, value: &str) -> Option<ParamValue> {
+        match *self {
+            ParamKind::UInt => value.parse().ok().map(ParamValue::UInt),
+            ParamKind::Float => value.parse().ok().map(ParamValue::Float),
+            ParamKind::Choice(choices) => {
+                choices.iter().find(|&&c| c == value).map(|&c| ParamValue::Choice(c))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ParamValue {
+    UInt(usize),
+    Float(f32),
+    Choice(&'static str),
+}
+
+/// A typed parameter that an app accepts on the command line as `name=value`.
+#[derive(Copy, Clone)]
+pub struct ParamDesc {
+    pub name: &'static str,
+    pub kind: ParamKind,
+    pub default: &'static str,
+    pub description: &'static str,
+}
+
+pub struct Params {
+    values: Vec<(&'static str, ParamValue)>,
+}
+
+#[allow(dead_code)]
+impl Params {
+    fn get(&self, name: &str) -> ParamValue {
+        match self.values.iter().find(|&&(n, _)| n == name) {
+            Some(&(_, value)) => value,
+            None => panic!("app requested undeclared parameter {}", name),
+        }
+    }
+
+    pub fn get_uint(&self, name: &str) -> usize {
+        match self.get(name) {
+            ParamValue::UInt(value) => value,
+            value => panic!("parameter {} is not a uint: {:?}", name, value),
+        }
+    }
+
+    pub fn get_float(&self, name: &str) -> f32 {
+        match self.get(name) {
+            ParamValue::Float(value) => value,
+            value => panic!("parameter {} is not a float: {:?}", name, value),
+        }
+    }
+
+    pub fn get_choice(&self, name: &str) -> &'static str {
+        match self.get(name) {
+            ParamValue::Choice(value) => value,
+            value => panic!("parameter {} is not a choice: {:?}", name, value),
+        }
+    }
+}
+
+/// Creates an app, or fails with a message, e.g. when a shader doesn't compile.
+pub type CreateFn = fn(&mut GfxContext, &Params) -> Result<Box<dyn App>, String>;
+
+/// Describes an app that can be launched by name.
+///
+/// Besides its name, an app may register patterns such as
+/// `clear{clear}_quad{quads}`, where each `{param}` placeholder
+/// captures the value of that parameter from the app name.
+/// Presets are the concrete names that suites match globs against.
+#[derive(Copy, Clone)]
+pub struct AppDesc {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub patterns: &'static [&'static str],
+    pub presets: &'static [&'static str],
+    pub params: &'static [ParamDesc],
+    pub create: CreateFn,
+}
+
+/// A fully resolved app name plus parameters, ready to be created.
+pub struct AppSpec {
+    pub name: String,
+    desc: AppDesc,
+    params: Params,
+}
+
+impl AppSpec {
+    pub fn create(&self, gfx: &mut GfxContext) -> Result<Box<dyn App>, String> {
+        (self.desc.create)(gfx, &self.params)
+    }
+}
+
+pub struct AppRegistry {
+    apps: Vec<AppDesc>,
+}
+
+impl AppRegistry {
+    pub fn new() -> AppRegistry {
+        AppRegistry {
+            apps: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, desc: AppDesc) {
+        debug_assert!(self.apps.iter().all(|app| app.name != desc.name));
+        self.apps.push(desc);
+    }
+
+    pub fn print_list(&self) {
+        for app in &self.apps {
+            println!("{} - {}", app.name, app.description);
+            if !app.patterns.is_empty() {
+                println!("    names: {}", app.patterns.join(", "));
+            }
+            if !app.presets.is_empty() {
+                println!("    presets: {}", app.presets.join(", "));
+            }
+            for param in app.params {
+                println!("    {}=<{}> (default {}) {}",
+                         param.name,
+                         param.kind.describe(),
+                         param.default,
+                         param.description);
+            }
+        }
+    }
+
+    /// Expand a suite entry into app names. Entries containing `*` or `?`
+    /// are matched against registered app names and presets; anything
+    /// else is returned as is.
+    pub fn expand(&self, entry: &str) -> Vec<String> {
+        if !entry.contains(['*', '?']) {
+            return vec![entry.to_owned()];
+        }
+
+        let mut names = Vec::new();
+        for app in &self.apps {
+            for name in Some(&app.name).into_iter().chain(app.presets) {
+                if glob_match(entry, name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names
+    }
+
+    /// Resolve an app from command line words, e.g. `clear1_quad7` or
+    /// `quad_bench clear=1 quads=7`. Words may also be passed as a
+    /// single space separated string.
+    pub fn resolve(&self, args: &[String]) -> Result<AppSpec, String> {
+        let words: Vec<&str> = args.iter().flat_map(|arg| arg.split_whitespace()).collect();
+        let (app_name, assignments) = match words.split_first() {
+            Some((app_name, assignments)) => (*app_name, assignments),
+            None => return Err("no app name given".to_owned()),
+        };
+
+        let (desc, captures) = self.find(app_name).ok_or_else(|| {
+            let names: Vec<&str> = self.apps.iter().map(|app| app.name).collect();
+            format!("unknown app '{}' (available: {}; use --list for details)",
+                    app_name,
+                    names.join(", "))
+        })?;
+
+        let mut params = Params {
+            values: Vec::new(),
+        };
+        for param in desc.params {
+            let value = param.kind.parse(param.default).expect("bad default parameter value");
+            params.values.push((param.name, value));
+        }
+
+        let assignments = assignments.iter().map(|assignment| {
+            let mut parts = assignment.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => Ok((name, value)),
+                _ => Err(format!("expected name=value, found '{}'", assignment)),
+            }
+        });
+
+        for assignment in captures.into_iter().map(Ok).chain(assignments) {
+            let (name, value) = assignment?;
+            let index = desc.params.iter().position(|param| param.name == name).ok_or_else(|| {
+                let names: Vec<&str> = desc.params.iter().map(|param| param.name).collect();
+                format!("app '{}' has no parameter '{}' (expected one of: {})",
+                        desc.name,
+                        name,
+                        names.join(", "))
+            })?;
+            let param = &desc.params[index];
+            params.values[index].1 = param.kind.parse(value).ok_or_else(|| {
+                format!("invalid value '{}' for parameter '{}' (expected {})",
+                        value,
+                        name,
+                        param.kind.describe())
+            })?;
+        }
+
+        Ok(AppSpec {
+            name: words.join(" "),
+            desc: *desc,
+            params,
+        })
+    }
+
+    fn find<'a>(&self, name: &'a str) -> Option<(&AppDesc, Vec<(&'static str, &'a str)>)> {
+        for app in &self.apps {
+            if app.name == name {
+                return Some((app, Vec::new()));
+            }
+        }
+        for app in &self.apps {
+            for pattern in app.patterns {
+                // Several patterns may match textually (e.g. `clear{clear}` and
+                // `clear{clear}_quad{quads}`), so only accept one whose captures
+                // are valid values for their parameters.
+                if let Some(captures) = match_pattern(pattern, name) {
+                    let valid = captures.iter().all(|&(param, value)| {
+                        app.params.iter().any(|p| p.name == param && p.kind.parse(value).is_some())
+                    });
+                    if valid {
+                        return Some((app, captures));
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Match `name` against a pattern with `{param}` placeholders, returning
+/// the captured text for each placeholder. A placeholder captures up to
+/// the next literal part of the pattern.
+fn match_pattern<'a>(pattern: &'static str, name: &'a str) -> Option<Vec<(&'static str, &'a str)>> {
+    let mut captures = Vec::new();
+    let mut pattern = pattern;
+    let mut name = name;
+
+    loop {
+        match pattern.find('{') {
+            Some(open) => {
+                let (literal, rest) = pattern.split_at(open);
+                if !name.starts_with(literal) {
+                    return None;
+                }
+                name = &name[literal.len()..];
+
+                let close = rest.find('}').expect("unterminated placeholder in app pattern");
+                let param = &rest[1..close];
+                pattern = &rest[close + 1..];
+
+                let end = match pattern.find('{') {
+                    Some(next) => &pattern[..next],
+                    None => pattern,
+                };
+                let len = if end.is_empty() {
+                    name.len()
+                } else {
+                    name.find(end)?
+                };
+                if len == 0 {
+                    return None;
+                }
+                captures.push((param, &name[..len]));
+                name = &name[len..];
+            }
+            None => {
+                return if pattern == name {
+                    Some(captures)
+                } else {
+                    None
+                };
+            }
+        }
+    }
+}
+
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // Iterative matcher that backtracks to the most recent `*`.
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_match, match_pattern, AppDesc, AppRegistry, ParamDesc, ParamKind, Params};
+    use app::App;
+    use gfx::GfxContext;
+
+    fn create(_: &mut GfxContext, _: &Params) -> Result<Box<dyn App>, String> {
+        Err("not created in tests".to_owned())
+    }
+
+    fn registry() -> AppRegistry {
+        let mut registry = AppRegistry::new();
+        registry.register(AppDesc {
+            name: "quads",
+            description: "",
+            patterns: &["clear{clear}", "clear{clear}_quad{quads}", "quad{quads}_{mode}"],
+            presets: &["clear1_quad8", "quad8_fast"],
+            params: &[
+                ParamDesc { name: "clear", kind: ParamKind::UInt, default: "0", description: "" },
+                ParamDesc { name: "quads", kind: ParamKind::UInt, default: "1", description: "" },
+                ParamDesc { name: "mode", kind: ParamKind::Choice(&["fast", "slow"]), default: "fast", description: "" },
+                ParamDesc { name: "scale", kind: ParamKind::Float, default: "1.0", description: "" },
+            ],
+            create,
+        });
+        registry.register(AppDesc {
+            name: "test",
+            description: "",
+            patterns: &[],
+            presets: &[],
+            params: &[],
+            create,
+        });
+        registry
+    }
+
+    fn resolve(registry: &AppRegistry, args: &str) -> Result<Params, String> {
+        registry.resolve(&[args.to_owned()]).map(|spec| spec.params)
+    }
+
+    fn resolve_error(registry: &AppRegistry, args: &str) -> String {
+        resolve(registry, args).err().expect("resolve should fail")
+    }
+
+    #[test]
+    fn match_pattern_captures_placeholders() {
+        assert_eq!(match_pattern("clear{clear}_quad{quads}", "clear1_quad7"),
+                   Some(vec![("clear", "1"), ("quads", "7")]));
+        assert_eq!(match_pattern("quad{quads}_{mode}", "quad12_slow"),
+                   Some(vec![("quads", "12"), ("mode", "slow")]));
+        assert_eq!(match_pattern("clear{clear}", "clear1_quad7"), Some(vec![("clear", "1_quad7")]));
+        assert_eq!(match_pattern("exact", "exact"), Some(vec![]));
+        // Captures are only text, and are checked against the parameter kinds later.
+        assert_eq!(match_pattern("clear{clear}_quad{quads}", "clear1_quads7"),
+                   Some(vec![("clear", "1"), ("quads", "s7")]));
+    }
+
+    #[test]
+    fn match_pattern_rejects_mismatches() {
+        assert_eq!(match_pattern("exact", "exactly"), None);
+        assert_eq!(match_pattern("clear{clear}_quad{quads}", "clear1_tri7"), None);
+        assert_eq!(match_pattern("clear{clear}_quad{quads}", "clear_quad7"), None);
+        assert_eq!(match_pattern("clear{clear}_quad{quads}", "clear1_quad"), None);
+        assert_eq!(match_pattern("quad{quads}_{mode}", "quad8"), None);
+    }
+
+    #[test]
+    fn glob_match_wildcards() {
+        assert!(glob_match("quad8_fast", "quad8_fast"));
+        assert!(!glob_match("quad8_fast", "quad8_faster"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("quad*", "quad8_fast"));
+        assert!(glob_match("*_fast", "quad8_fast"));
+        assert!(glob_match("q*8*t", "quad8_fast"));
+        assert!(glob_match("quad?_fast", "quad8_fast"));
+        assert!(!glob_match("quad?_fast", "quad16_fast"));
+        assert!(!glob_match("?", ""));
+        assert!(!glob_match("*slow", "quad8_fast"));
+    }
+
+    #[test]
+    fn expand_matches_names_and_presets() {
+        let registry = registry();
+        assert_eq!(registry.expand("quad*"), vec!["quads", "quad8_fast"]);
+        assert_eq!(registry.expand("clear?_quad8"), vec!["clear1_quad8"]);
+        assert_eq!(registry.expand("*"), vec!["quads", "clear1_quad8", "quad8_fast", "test"]);
+        assert!(registry.expand("missing*").is_empty());
+        assert_eq!(registry.expand("not_a_glob"), vec!["not_a_glob"]);
+    }
+
+    #[test]
+    fn resolve_exact_names_and_patterns() {
+        let registry = registry();
+
+        let params = resolve(&registry, "quads").unwrap();
+        assert_eq!(params.get_uint("clear"), 0);
+        assert_eq!(params.get_choice("mode"), "fast");
+
+        let params = resolve(&registry, "clear1_quad7").unwrap();
+        assert_eq!(params.get_uint("clear"), 1);
+        assert_eq!(params.get_uint("quads"), 7);
+
+        // clear{clear} matches first textually, but "1_quad7" is no uint.
+        let params = resolve(&registry, "quad3_slow scale=0.5").unwrap();
+        assert_eq!(params.get_uint("quads"), 3);
+        assert_eq!(params.get_choice("mode"), "slow");
+        assert_eq!(params.get_float("scale"), 0.5);
+
+        // Assignments override captured values.
+        let params = resolve(&registry, "clear1_quad7 quads=2").unwrap();
+        assert_eq!(params.get_uint("quads"), 2);
+
+        assert!(resolve(&registry, "test").is_ok());
+    }
+
+    #[test]
+    fn resolve_rejects_unknown_names_and_values() {
+        let registry = registry();
+        assert!(resolve(&registry, "").is_err());
+        assert!(resolve_error(&registry, "missing").contains("unknown app 'missing'"));
+        assert!(resolve(&registry, "quad3_medium").is_err());
+        assert!(resolve(&registry, "clearx").is_err());
+        assert!(resolve(&registry, "clear1_quads7").is_err());
+        assert!(resolve_error(&registry, "quads speed=2").contains("no parameter 'speed'"));
+        assert!(resolve_error(&registry, "quads quads=-1").contains("invalid value"));
+        assert!(resolve_error(&registry, "quads quads").contains("expected name=value"));
+    }
+}
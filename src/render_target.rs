@@ -0,0 +1,109 @@
+use gleam::gl;
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RenderTargetFormat {
+    Rgba8,
+    Rgba16F,
+    Rgba32F,
+}
+
+impl RenderTargetFormat {
+    fn gl_formats(&self) -> (gl::GLenum, gl::GLenum) {
+        match *self {
+            RenderTargetFormat::Rgba8 => (gl::RGBA8, gl::UNSIGNED_BYTE),
+            RenderTargetFormat::Rgba16F => (gl::RGBA16F, gl::HALF_FLOAT),
+            RenderTargetFormat::Rgba32F => (gl::RGBA32F, gl::FLOAT),
+        }
+    }
+}
+
+/// A texture with a framebuffer object to draw into it.
+pub struct RenderTarget {
+    pub fbo: gl::GLuint,
+    pub texture: gl::GLuint,
+    pub width: u32,
+    pub height: u32,
+    format: RenderTargetFormat,
+}
+
+impl RenderTarget {
+    pub fn new(width: u32, height: u32, format: RenderTargetFormat) -> Result<RenderTarget, String> {
+        let texture = gl::gen_textures(1)[0];
+        gl::bind_texture(gl::TEXTURE_2D, texture);
+        gl::tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as gl::GLint);
+        gl::tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as gl::GLint);
+        gl::tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as gl::GLint);
+        gl::tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as gl::GLint);
+        gl::bind_texture(gl::TEXTURE_2D, 0);
+
+        let mut target = RenderTarget {
+            fbo: gl::gen_framebuffers(1)[0],
+            texture,
+            width: 0,
+            height: 0,
+            format,
+        };
+        target.resize(width, height)?;
+        Ok(target)
+    }
+
+    /// Reallocate the texture at a new size. The contents are undefined
+    /// afterwards. Leaves the target's framebuffer bound. If the new size
+    /// is rejected, the target keeps storage of its old size.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+        let max_size = gl::get_integer_v(gl::MAX_TEXTURE_SIZE) as u32;
+        if width == 0 || height == 0 || width > max_size || height > max_size {
+            return Err(format!("{}x{} render target is outside the supported sizes (1 to {})",
+                               width,
+                               height,
+                               max_size));
+        }
+
+        let status = self.allocate(width, height);
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            let (old_width, old_height) = (self.width, self.height);
+            self.allocate(old_width, old_height);
+            return Err(format!("{}x{} {:?} render target is incomplete (status 0x{:x})",
+                               width,
+                               height,
+                               self.format,
+                               status));
+        }
+
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
+    // Returns the framebuffer status with the new storage attached.
+    fn allocate(&self, width: u32, height: u32) -> gl::GLenum {
+        let (internal_format, ty) = self.format.gl_formats();
+        gl::bind_texture(gl::TEXTURE_2D, self.texture);
+        gl::tex_image_2d(gl::TEXTURE_2D,
+                         0,
+                         internal_format as gl::GLint,
+                         width as gl::GLint,
+                         height as gl::GLint,
+                         0,
+                         gl::RGBA,
+                         ty,
+                         None);
+        gl::bind_texture(gl::TEXTURE_2D, 0);
+
+        gl::bind_framebuffer(gl::FRAMEBUFFER, self.fbo);
+        gl::framebuffer_texture_2d(gl::FRAMEBUFFER,
+                                   gl::COLOR_ATTACHMENT0,
+                                   gl::TEXTURE_2D,
+                                   self.texture,
+                                   0);
+        unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) }
+    }
+}
+
+impl Drop for RenderTarget {
+    fn drop(&mut self) {
+        gl::delete_framebuffers(&[self.fbo]);
+        gl::delete_textures(&[self.texture]);
+    }
+}
@@ -0,0 +1,369 @@
+use gleam::gl;
+use json::Json;
+use shader_cache::ShaderStats;
+use stats::{FrameStats, FrameTiming, Summary};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use upload::{UploadMethod, UploadStats};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReportFormat {
+    Json,
+    Csv,
+}
+
+impl ReportFormat {
+    pub fn parse(name: &str) -> Option<ReportFormat> {
+        match name {
+            "json" => Some(ReportFormat::Json),
+            "csv" => Some(ReportFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// Describes the GL implementation and sandpit build a report was made with.
+#[derive(Debug, Clone)]
+pub struct Environment {
+    pub renderer: String,
+    pub vendor: String,
+    pub version: String,
+    pub git_commit: String,
+}
+
+impl Environment {
+    /// Requires a current GL context.
+    pub fn query() -> Environment {
+        Environment {
+            renderer: gl::get_string(gl::RENDERER),
+            vendor: gl::get_string(gl::VENDOR),
+            version: gl::get_string(gl::VERSION),
+            git_commit: env!("GIT_COMMIT").to_owned(),
+        }
+    }
+
+    pub fn unknown() -> Environment {
+        Environment {
+            renderer: "unknown".to_owned(),
+            vendor: "unknown".to_owned(),
+            version: "unknown".to_owned(),
+            git_commit: "unknown".to_owned(),
+        }
+    }
+}
+
+/// The results of running a single app.
+pub struct AppRecord {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub warmup: usize,
+    pub start_time: String,
+    pub end_time: String,
+    pub stats: FrameStats,
+    pub shaders: ShaderStats,
+    pub uploads: UploadStats,
+    /// From `App::metadata`.
+    pub metadata: Vec<(String, f64)>,
+}
+
+pub struct Report {
+    pub environment: Environment,
+    pub records: Vec<AppRecord>,
+}
+
+fn summary_to_json(summary: &Summary) -> Json {
+    let mut json = Json::object();
+    json.set("count", summary.count);
+    json.set("min_ms", summary.min);
+    json.set("max_ms", summary.max);
+    json.set("mean_ms", summary.mean);
+    json.set("median_ms", summary.median);
+    json.set("p95_ms", summary.p95);
+    json.set("p99_ms", summary.p99);
+    json.set("stddev_ms", summary.stddev);
+    json.set("outliers", summary.outliers);
+    json
+}
+
+fn shaders_to_json(shaders: &ShaderStats) -> Json {
+    let mut json = Json::object();
+    json.set("compiled", shaders.compiled);
+    json.set("compile_ms", shaders.compile_ns as f64 / 1000000.0);
+    json.set("cache_hits", shaders.cache_hits);
+    json.set("cache_hit_ms", shaders.cache_hit_ns as f64 / 1000000.0);
+    json.set("cache_rejected", shaders.cache_rejected);
+    json
+}
+
+// Reports written before shader stats were recorded have none.
+fn shaders_from_json(json: Option<&Json>) -> ShaderStats {
+    let number = |key: &str| json.and_then(|j| j.get(key)).and_then(|v| v.as_f64()).unwrap_or(0.0);
+    ShaderStats {
+        compiled: number("compiled") as usize,
+        compile_ns: (number("compile_ms") * 1000000.0) as u64,
+        cache_hits: number("cache_hits") as usize,
+        cache_hit_ns: (number("cache_hit_ms") * 1000000.0) as u64,
+        cache_rejected: number("cache_rejected") as usize,
+    }
+}
+
+fn uploads_to_json(uploads: &UploadStats) -> Json {
+    let mut json = Json::object();
+    json.set("method", uploads.method.name());
+    json.set("bytes", uploads.bytes);
+    json.set("waits", uploads.waits);
+    json.set("wait_ms", uploads.wait_ns as f64 / 1000000.0);
+    json
+}
+
+// Reports written before upload stats were recorded used direct uploads.
+fn uploads_from_json(json: Option<&Json>) -> UploadStats {
+    let number = |key: &str| json.and_then(|j| j.get(key)).and_then(|v| v.as_f64()).unwrap_or(0.0);
+    UploadStats {
+        method: json.and_then(|j| j.get("method"))
+                    .and_then(|v| v.as_str())
+                    .and_then(UploadMethod::parse)
+                    .unwrap_or(UploadMethod::Direct),
+        bytes: number("bytes") as u64,
+        waits: number("waits") as usize,
+        wait_ns: (number("wait_ms") * 1000000.0) as u64,
+    }
+}
+
+fn metadata_to_json(metadata: &[(String, f64)]) -> Json {
+    let mut json = Json::object();
+    for (key, value) in metadata {
+        json.set(key, *value);
+    }
+    json
+}
+
+// Reports written before app metadata was recorded have none.
+fn metadata_from_json(json: Option<&Json>) -> Result<Vec<(String, f64)>, String> {
+    let members = match json.and_then(|j| j.members()) {
+        Some(members) => members,
+        None => return Ok(Vec::new()),
+    };
+    members.iter().map(|(key, value)| {
+        value.as_f64().map(|v| (key.clone(), v)).ok_or_else(|| format!("metadata.{} must be a number", key))
+    }).collect()
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+impl Report {
+    pub fn new(environment: Environment) -> Report {
+        Report {
+            environment,
+            records: Vec::new(),
+        }
+    }
+
+    fn record_to_json(&self, record: &AppRecord) -> Json {
+        let env = &self.environment;
+        let stats = &record.stats;
+
+        let mut summary = Json::object();
+        summary.set("total", summary_to_json(&stats.total()));
+        summary.set("cpu", summary_to_json(&stats.cpu()));
+        summary.set("swap", summary_to_json(&stats.swap()));
+        summary.set("gpu", summary_to_json(&stats.gpu()));
+        let mut gpu_scopes = Json::object();
+        for (name, scope) in stats.gpu_scopes() {
+            gpu_scopes.set(name, summary_to_json(&scope));
+        }
+        summary.set("gpu_scopes", gpu_scopes);
+
+        let mut samples = Json::object();
+        samples.set("total_ns", stats.frames.iter().map(|t| t.total_ns).collect::<Vec<_>>());
+        samples.set("cpu_ns", stats.frames.iter().map(|t| t.cpu_ns).collect::<Vec<_>>());
+        samples.set("swap_ns", stats.frames.iter().map(|t| t.swap_ns).collect::<Vec<_>>());
+        samples.set("gpu_ns", stats.gpu_frames.clone());
+        let mut gpu_scopes = Json::object();
+        for (name, scope_samples) in &stats.gpu_scopes {
+            gpu_scopes.set(name, scope_samples.clone());
+        }
+        samples.set("gpu_scopes", gpu_scopes);
+
+        let mut json = Json::object();
+        json.set("name", record.name.as_str());
+        json.set("renderer", env.renderer.as_str());
+        json.set("vendor", env.vendor.as_str());
+        json.set("version", env.version.as_str());
+        json.set("git_commit", env.git_commit.as_str());
+        json.set("width", record.width);
+        json.set("height", record.height);
+        json.set("start_time", record.start_time.as_str());
+        json.set("end_time", record.end_time.as_str());
+        json.set("warmup", record.warmup);
+        json.set("frames", stats.frames.len());
+        json.set("summary", summary);
+        json.set("samples", samples);
+        json.set("shaders", shaders_to_json(&record.shaders));
+        json.set("uploads", uploads_to_json(&record.uploads));
+        json.set("metadata", metadata_to_json(&record.metadata));
+        json
+    }
+
+    pub fn load(path: &Path) -> Result<Report, String> {
+        let mut text = String::new();
+        File::open(path).and_then(|mut f| f.read_to_string(&mut text))
+                        .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        let json = Json::parse(&text)?;
+        Report::from_json(&json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Rebuild a report from JSON written by `to_json`. The environment is
+    /// taken from the first record.
+    pub fn from_json(json: &Json) -> Result<Report, String> {
+        let apps = json.get("apps").and_then(|a| a.as_array()).ok_or("missing apps array")?;
+
+        let string = |record: &Json, key: &str| {
+            record.get(key).and_then(|v| v.as_str()).map(|v| v.to_owned())
+                  .ok_or_else(|| format!("missing string field {}", key))
+        };
+        let number = |record: &Json, key: &str| {
+            record.get(key).and_then(|v| v.as_f64())
+                  .ok_or_else(|| format!("missing number field {}", key))
+        };
+        let samples = |json: &Json| -> Result<Vec<u64>, String> {
+            let values = json.as_array().ok_or("samples must be an array")?;
+            values.iter().map(|v| v.as_f64().map(|v| v as u64).ok_or_else(|| "bad sample".to_owned()))
+                  .collect()
+        };
+
+        let mut report = Report::new(Environment::unknown());
+
+        for (i, app) in apps.iter().enumerate() {
+            if i == 0 {
+                report.environment = Environment {
+                    renderer: string(app, "renderer")?,
+                    vendor: string(app, "vendor")?,
+                    version: string(app, "version")?,
+                    git_commit: string(app, "git_commit")?,
+                };
+            }
+
+            let json_samples = app.get("samples").ok_or("missing samples")?;
+            let sample_field = |key: &str| {
+                json_samples.get(key).ok_or_else(|| format!("missing samples.{}", key)).and_then(&samples)
+            };
+            let total = sample_field("total_ns")?;
+            let cpu = sample_field("cpu_ns")?;
+            let swap = sample_field("swap_ns")?;
+            if cpu.len() != total.len() || swap.len() != total.len() {
+                return Err("mismatched sample counts".to_owned());
+            }
+
+            let mut stats = FrameStats::new();
+            for i in 0..total.len() {
+                stats.push(FrameTiming {
+                    cpu_ns: cpu[i],
+                    swap_ns: swap[i],
+                    total_ns: total[i],
+                });
+            }
+            stats.gpu_frames = sample_field("gpu_ns")?;
+            if let Some(scopes) = json_samples.get("gpu_scopes").and_then(|s| s.members()) {
+                for (name, scope_samples) in scopes {
+                    stats.gpu_scopes.push((name.clone(), samples(scope_samples)?));
+                }
+            }
+
+            report.records.push(AppRecord {
+                name: string(app, "name")?,
+                width: number(app, "width")? as u32,
+                height: number(app, "height")? as u32,
+                warmup: number(app, "warmup")? as usize,
+                start_time: string(app, "start_time")?,
+                end_time: string(app, "end_time")?,
+                stats,
+                shaders: shaders_from_json(app.get("shaders")),
+                uploads: uploads_from_json(app.get("uploads")),
+                metadata: metadata_from_json(app.get("metadata"))?,
+            });
+        }
+
+        Ok(report)
+    }
+
+    pub fn to_json(&self) -> Json {
+        let mut json = Json::object();
+        json.set("apps", Json::Array(self.records.iter().map(|r| self.record_to_json(r)).collect()));
+        json
+    }
+
+    /// One row per app and metric, with summary statistics only. App
+    /// metadata is a single column of `key=value` pairs.
+    pub fn to_csv(&self) -> String {
+        let env = &self.environment;
+        let mut csv = String::from("name,renderer,vendor,version,git_commit,width,height,\
+                                    start_time,end_time,warmup,metric,count,min_ms,max_ms,\
+                                    mean_ms,median_ms,p95_ms,p99_ms,stddev_ms,outliers,metadata\n");
+
+        for record in &self.records {
+            let stats = &record.stats;
+            let mut metrics = vec![
+                ("total".to_owned(), stats.total()),
+                ("cpu".to_owned(), stats.cpu()),
+                ("swap".to_owned(), stats.swap()),
+                ("gpu".to_owned(), stats.gpu()),
+            ];
+            for (name, summary) in stats.gpu_scopes() {
+                metrics.push((format!("gpu:{}", name), summary));
+            }
+            let metadata: Vec<String> = record.metadata.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+            let metadata = csv_field(&metadata.join(";"));
+
+            for (metric, s) in metrics {
+                let fields = [
+                    csv_field(&record.name),
+                    csv_field(&env.renderer),
+                    csv_field(&env.vendor),
+                    csv_field(&env.version),
+                    csv_field(&env.git_commit),
+                    record.width.to_string(),
+                    record.height.to_string(),
+                    csv_field(&record.start_time),
+                    csv_field(&record.end_time),
+                    record.warmup.to_string(),
+                    csv_field(&metric),
+                    s.count.to_string(),
+                    s.min.to_string(),
+                    s.max.to_string(),
+                    s.mean.to_string(),
+                    s.median.to_string(),
+                    s.p95.to_string(),
+                    s.p99.to_string(),
+                    s.stddev.to_string(),
+                    s.outliers.to_string(),
+                    metadata.clone(),
+                ];
+                csv.push_str(&fields.join(","));
+                csv.push('\n');
+            }
+        }
+
+        csv
+    }
+
+    /// Write the report to `path`, or to stdout if no path is given.
+    pub fn write(&self, format: ReportFormat, path: Option<&Path>) -> io::Result<()> {
+        let output = match format {
+            ReportFormat::Json => format!("{}\n", self.to_json()),
+            ReportFormat::Csv => self.to_csv(),
+        };
+        match path {
+            Some(path) => File::create(path)?.write_all(output.as_bytes()),
+            None => io::stdout().write_all(output.as_bytes()),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use app::{AppKind, KeyCode};
+use backend::Backend;
+use gfx::GfxContext;
+use glutin;
+use input::InputState;
+use options::Options;
+use registry::AppSpec;
+use report::{AppRecord, Environment};
+use stats::{FrameStats, FrameTiming};
+use std::path::{Path, PathBuf};
+use time;
+use time::precise_time_ns;
+use util;
+
+fn save_screenshot(gfx: &GfxContext, path: &Path, width: u32, height: u32) {
+    let pixels = gfx.read_pixels(width, height);
+    match util::save_png(path, &pixels, width, height) {
+        Ok(()) => eprintln!("Saved screenshot to {}", path.display()),
+        Err(e) => eprintln!("Failed to save screenshot: {}", e),
+    }
+}
+
+pub fn run_app(backend: &Backend,
+               gfx: &mut GfxContext,
+               spec: &AppSpec,
+               options: &Options) -> Result<AppRecord, String> {
+    let mut app = spec.create(gfx)?;
+    let mut input = backend.window().map(InputState::new);
+
+    let start_time = time::now_utc();
+    let mut stats = FrameStats::new();
+    let mut frame_count = 0;
+
+    // Headless and non-interactive runs have no events to wait for, so
+    // test apps just draw a fixed number of frames. Only benchmarks warm up.
+    let interactive = options.interactive && backend.window().is_some();
+    let (warmup, frame_limit) = match app.kind() {
+        AppKind::Test if interactive => (0, options.frames),
+        AppKind::Test => (0, Some(options.frames.unwrap_or(1))),
+        AppKind::Benchmark => (options.warmup, Some(options.frames.unwrap_or(500))),
+    };
+
+    // Screenshots default to the last frame, counting warmup frames.
+    let screenshot_path = options.screenshot.clone().unwrap_or_else(|| PathBuf::from("screenshot.png"));
+    let screenshot_frame = match (&options.screenshot, options.screenshot_frame) {
+        (_, Some(frame)) => Some(frame),
+        (Some(_), None) => frame_limit.map(|limit| warmup + limit),
+        (None, None) => None,
+    };
+
+    loop {
+        if Some(stats.frames.len()) == frame_limit {
+            break;
+        }
+
+        let mut screenshot_requested = false;
+        if let (AppKind::Test, true, Some(window), Some(input)) = (app.kind(), interactive, backend.window(), input.as_mut()) {
+            match window.wait_events().next().unwrap() {
+                glutin::Event::Closed => break,
+                glutin::Event::KeyboardInput(glutin::ElementState::Pressed, _, Some(KeyCode::Escape)) => break,
+                glutin::Event::KeyboardInput(glutin::ElementState::Pressed, _, Some(KeyCode::F12)) => {
+                    screenshot_requested = true;
+                }
+                event => {
+                    for event in input.translate(window, event) {
+                        app.on_event(event);
+                    }
+                }
+            }
+        }
+
+        let frame_start = precise_time_ns();
+
+        let (width, height) = backend.size();
+        gfx.begin_frame(width, height)?;
+        app.draw(gfx, width, height);
+        gfx.end_frame();
+
+        if screenshot_requested || screenshot_frame == Some(frame_count + 1) {
+            save_screenshot(gfx, &screenshot_path, width, height);
+        }
+
+        let swap_start = precise_time_ns();
+        backend.swap_buffers();
+        let frame_end = precise_time_ns();
+
+        frame_count += 1;
+        if frame_count > warmup {
+            stats.push(FrameTiming {
+                cpu_ns: swap_start - frame_start,
+                swap_ns: frame_end - swap_start,
+                total_ns: frame_end - frame_start,
+            });
+        }
+
+        for timings in gfx.take_gpu_timings(false) {
+            if timings.frame_index >= warmup {
+                stats.push_gpu(timings);
+            }
+        }
+    }
+
+    for timings in gfx.take_gpu_timings(true) {
+        if timings.frame_index >= warmup {
+            stats.push_gpu(timings);
+        }
+    }
+
+    let metadata = app.metadata();
+    app.deinit(gfx);
+
+    let (width, height) = backend.size();
+    Ok(AppRecord {
+        name: spec.name.clone(),
+        width,
+        height,
+        warmup,
+        start_time: start_time.rfc3339().to_string(),
+        end_time: time::now_utc().rfc3339().to_string(),
+        stats,
+        shaders: gfx.shader_stats(),
+        uploads: gfx.upload_stats(),
+        metadata,
+    })
+}
+
+pub fn print_record(record: &AppRecord, environment: &Environment) {
+    let stats = &record.stats;
+    let total = stats.total();
+    println!("{} frames={} warmup={} total={:.3}ms avg={:.3}ms",
+             record.name,
+             total.count,
+             record.warmup,
+             total.mean * total.count as f64,
+             total.mean);
+    println!("    gl:    {} / {} / {}", environment.renderer, environment.version, environment.vendor);
+    println!("    total: {}", total);
+    println!("    cpu:   {}", stats.cpu());
+    println!("    swap:  {}", stats.swap());
+    println!("    gpu:   {}", stats.gpu());
+    for (name, summary) in stats.gpu_scopes() {
+        println!("    gpu {}: {}", name, summary);
+    }
+    let shaders = &record.shaders;
+    println!("    shaders: {} compiled in {:.3}ms, {} from cache in {:.3}ms, {} stale",
+             shaders.compiled,
+             shaders.compile_ns as f64 / 1000000.0,
+             shaders.cache_hits,
+             shaders.cache_hit_ns as f64 / 1000000.0,
+             shaders.cache_rejected);
+    let uploads = &record.uploads;
+    println!("    uploads: {} {:.3}MB, {} waits in {:.3}ms",
+             uploads.method.name(),
+             uploads.bytes as f64 / (1024.0 * 1024.0),
+             uploads.waits,
+             uploads.wait_ns as f64 / 1000000.0);
+    if !record.metadata.is_empty() {
+        let values: Vec<String> = record.metadata.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        println!("    metadata: {}", values.join(" "));
+    }
+}
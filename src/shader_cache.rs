@@ -0,0 +1,299 @@
+use gleam::gl;
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::mem;
+use std::os::raw::c_void;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use time::precise_time_ns;
+
+// Program binaries are GL 4.1 / ARB_get_program_binary, which the GL 3.3
+// bindings in gleam don't include, so the entry points are loaded here.
+const PROGRAM_BINARY_RETRIEVABLE_HINT: gl::GLenum = 0x8257;
+const PROGRAM_BINARY_LENGTH: gl::GLenum = 0x8741;
+const NUM_PROGRAM_BINARY_FORMATS: gl::GLenum = 0x87FE;
+
+type GetProgramBinaryFn = extern "system" fn(gl::GLuint,
+                                             gl::GLsizei,
+                                             *mut gl::GLsizei,
+                                             *mut gl::GLenum,
+                                             *mut c_void);
+type ProgramBinaryFn = extern "system" fn(gl::GLuint, gl::GLenum, *const c_void, gl::GLsizei);
+type ProgramParameteriFn = extern "system" fn(gl::GLuint, gl::GLenum, gl::GLint);
+
+static GET_PROGRAM_BINARY: AtomicUsize = AtomicUsize::new(0);
+static PROGRAM_BINARY: AtomicUsize = AtomicUsize::new(0);
+static PROGRAM_PARAMETERI: AtomicUsize = AtomicUsize::new(0);
+
+/// Load the program binary entry points. Called alongside `gl::load_with`.
+pub fn load_with<F>(mut get_proc_address: F) where F: FnMut(&str) -> *const c_void {
+    GET_PROGRAM_BINARY.store(get_proc_address("glGetProgramBinary") as usize, Ordering::Relaxed);
+    PROGRAM_BINARY.store(get_proc_address("glProgramBinary") as usize, Ordering::Relaxed);
+    PROGRAM_PARAMETERI.store(get_proc_address("glProgramParameteri") as usize, Ordering::Relaxed);
+}
+
+fn entry_points() -> Option<(GetProgramBinaryFn, ProgramBinaryFn, ProgramParameteriFn)> {
+    let get_program_binary = GET_PROGRAM_BINARY.load(Ordering::Relaxed);
+    let program_binary = PROGRAM_BINARY.load(Ordering::Relaxed);
+    let program_parameteri = PROGRAM_PARAMETERI.load(Ordering::Relaxed);
+    if get_program_binary == 0 || program_binary == 0 || program_parameteri == 0 {
+        return None;
+    }
+    unsafe {
+        Some((mem::transmute::<usize, GetProgramBinaryFn>(get_program_binary),
+              mem::transmute::<usize, ProgramBinaryFn>(program_binary),
+              mem::transmute::<usize, ProgramParameteriFn>(program_parameteri)))
+    }
+}
+
+fn supported() -> bool {
+    entry_points().is_some() && gl::get_integer_v(NUM_PROGRAM_BINARY_FORMATS) > 0
+}
+
+// FNV-1a, which unlike the std hashers is stable between Rust releases.
+fn hash(data: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for s in data {
+        for byte in s.bytes().chain(Some(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+/// Time spent building programs, split by whether they came from source
+/// or from the binary cache.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct ShaderStats {
+    pub compiled: usize,
+    pub compile_ns: u64,
+    pub cache_hits: usize,
+    pub cache_hit_ns: u64,
+    pub cache_rejected: usize,
+}
+
+/// Stores linked program binaries on disk. Each program and set of
+/// defines has one entry, which also records a hash of the preprocessed
+/// source and the GL implementation, so an edited shader replaces its old
+/// binary rather than adding another. The cache directory is
+/// `$GL_SANDPIT_SHADER_CACHE`, or `gl_sandpit_shaders` in the temp dir.
+pub struct ShaderCache {
+    dir: Option<PathBuf>,
+    implementation: String,
+    pub stats: ShaderStats,
+}
+
+impl ShaderCache {
+    /// Requires a current GL context.
+    pub fn new() -> ShaderCache {
+        let dir = if supported() {
+            let dir = env::var_os("GL_SANDPIT_SHADER_CACHE")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| env::temp_dir().join("gl_sandpit_shaders"));
+            fs::create_dir_all(&dir).ok().map(|_| dir)
+        } else {
+            None
+        };
+        ShaderCache::with_dir(dir)
+    }
+
+    fn with_dir(dir: Option<PathBuf>) -> ShaderCache {
+        ShaderCache {
+            dir,
+            implementation: format!("{}\n{}\n{}",
+                                    gl::get_string(gl::VENDOR),
+                                    gl::get_string(gl::RENDERER),
+                                    gl::get_string(gl::VERSION)),
+            stats: ShaderStats::default(),
+        }
+    }
+
+    fn entry_path(&self, name: &Path, defines: &[String]) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        let defines = defines.join("\n");
+        let key = hash(&[&name.to_string_lossy(), &defines]);
+        Some(dir.join(format!("{:016x}.bin", key)))
+    }
+
+    /// Build the program `name` through the cache, calling `link` on a miss.
+    pub fn get_or_link<F, E>(&mut self,
+                             name: &Path,
+                             source: &str,
+                             defines: &[String],
+                             link: F) -> Result<gl::GLuint, E>
+                             where F: FnOnce() -> Result<gl::GLuint, E> {
+        let start = precise_time_ns();
+        let path = self.entry_path(name, defines);
+        let content = hash(&[&self.implementation, source]);
+
+        if let Some(ref path) = path {
+            if let Some(program) = self.load(path, content) {
+                self.stats.cache_hits += 1;
+                self.stats.cache_hit_ns += precise_time_ns() - start;
+                return Ok(program);
+            }
+        }
+
+        let program = link()?;
+        self.stats.compiled += 1;
+        self.stats.compile_ns += precise_time_ns() - start;
+
+        if let Some(ref path) = path {
+            if let Err(e) = store(path, content, program) {
+                eprintln!("Failed to cache program binary {}: {}", path.display(), e);
+            }
+        }
+        Ok(program)
+    }
+
+    fn load(&mut self, path: &Path, content: u64) -> Option<gl::GLuint> {
+        let (_, program_binary, _) = entry_points()?;
+
+        let mut data = Vec::new();
+        File::open(path).and_then(|mut f| f.read_to_end(&mut data)).ok()?;
+        if data.len() < 12 {
+            return None;
+        }
+        let mut stored_content = [0u8; 8];
+        stored_content.copy_from_slice(&data[..8]);
+        if u64::from_le_bytes(stored_content) != content {
+            // Built from an older source, and overwritten once relinked.
+            self.stats.cache_rejected += 1;
+            return None;
+        }
+        let mut format = [0u8; 4];
+        format.copy_from_slice(&data[8..12]);
+        let format = u32::from_le_bytes(format);
+        let binary = &data[12..];
+
+        let program = gl::create_program();
+        program_binary(program,
+                       format,
+                       binary.as_ptr() as *const c_void,
+                       binary.len() as gl::GLsizei);
+
+        // Drivers reject binaries from other builds of themselves, so a
+        // rejected entry is stale rather than an error.
+        if gl::get_program_iv(program, gl::LINK_STATUS) == (0 as gl::GLint) {
+            gl::delete_program(program);
+            let _ = fs::remove_file(path);
+            self.stats.cache_rejected += 1;
+            return None;
+        }
+        Some(program)
+    }
+}
+
+/// Mark a program as retrievable before it's linked, if binaries are supported.
+pub fn set_retrievable(program: gl::GLuint) {
+    if let Some((_, _, program_parameteri)) = entry_points() {
+        program_parameteri(program, PROGRAM_BINARY_RETRIEVABLE_HINT, gl::TRUE as gl::GLint);
+    }
+}
+
+fn store(path: &Path, content: u64, program: gl::GLuint) -> Result<(), String> {
+    let (get_program_binary, _, _) = entry_points().ok_or("program binaries unsupported")?;
+
+    let length = gl::get_program_iv(program, PROGRAM_BINARY_LENGTH);
+    if length <= 0 {
+        return Err("driver returned an empty binary".to_owned());
+    }
+    let mut binary = vec![0u8; length as usize];
+    let mut written = 0;
+    let mut format = 0;
+    get_program_binary(program,
+                       length,
+                       &mut written,
+                       &mut format,
+                       binary.as_mut_ptr() as *mut c_void);
+    binary.truncate(written as usize);
+
+    let mut data = Vec::with_capacity(binary.len() + 12);
+    data.extend_from_slice(&content.to_le_bytes());
+    data.extend_from_slice(&format.to_le_bytes());
+    data.extend_from_slice(&binary);
+
+    // Write to a temporary file first so that concurrent suite processes
+    // never see a partial entry.
+    let temp_path = path.with_extension(format!("{}.tmp", ::std::process::id()));
+    File::create(&temp_path).and_then(|mut f| f.write_all(&data))
+                            .and_then(|_| fs::rename(&temp_path, path))
+                            .map_err(|e| {
+                                let _ = fs::remove_file(&temp_path);
+                                e.to_string()
+                            })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShaderCache;
+    use backend::Backend;
+    use gleam::gl;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::process;
+
+    fn link(source: &str) -> Result<gl::GLuint, String> {
+        let program = gl::create_program();
+        for &(stage_define, shader_type) in &[("VERTEX_SHADER", gl::VERTEX_SHADER),
+                                              ("FRAGMENT_SHADER", gl::FRAGMENT_SHADER)] {
+            let shader = gl::create_shader(shader_type);
+            let text = format!("#version 150\n#define {}\n{}", stage_define, source);
+            gl::shader_source(shader, &[text.as_bytes()]);
+            gl::compile_shader(shader);
+            gl::attach_shader(program, shader);
+            gl::delete_shader(shader);
+        }
+        super::set_retrievable(program);
+        gl::link_program(program);
+        if gl::get_program_iv(program, gl::LINK_STATUS) == (0 as gl::GLint) {
+            return Err(gl::get_program_info_log(program));
+        }
+        Ok(program)
+    }
+
+    fn source(color: f32) -> String {
+        format!("#ifdef VERTEX_SHADER\n\
+                 void main() {{ gl_Position = vec4(0.0); }}\n\
+                 #else\n\
+                 out vec4 oFragColor;\n\
+                 void main() {{ oFragColor = vec4({:.1}); }}\n\
+                 #endif\n",
+                color)
+    }
+
+    #[test]
+    fn an_edited_program_replaces_its_entry() {
+        let backend = match Backend::new_headless(16, 16) {
+            Ok(backend) => backend,
+            Err(e) => {
+                eprintln!("skipping, no headless GL context: {}", e);
+                return;
+            }
+        };
+        if !super::supported() {
+            eprintln!("skipping, program binaries are unsupported");
+            return;
+        }
+        let dir = env::temp_dir().join(format!("gl_sandpit_shader_cache_test_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut cache = ShaderCache::with_dir(Some(dir.clone()));
+
+        let name = Path::new("edited.glsl");
+        for &color in &[0.0, 1.0, 1.0] {
+            let program = cache.get_or_link(name, &source(color), &[], || link(&source(color))).unwrap();
+            gl::delete_program(program);
+        }
+        let entries = fs::read_dir(&dir).unwrap().count();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(entries, 1);
+        assert_eq!(cache.stats.compiled, 2);
+        assert_eq!(cache.stats.cache_rejected, 1);
+        assert_eq!(cache.stats.cache_hits, 1);
+        drop(backend);
+    }
+}
@@ -0,0 +1,267 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+impl ShaderStage {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ShaderStage::Vertex => "vertex",
+            ShaderStage::Fragment => "fragment",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ShaderErrorKind {
+    Preprocess,
+    Compile(ShaderStage),
+    Link,
+}
+
+/// A single message from a driver info log, mapped back to the file it
+/// refers to when the driver reports a location.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShaderError {
+    pub kind: ShaderErrorKind,
+    pub path: PathBuf,
+    pub defines: Vec<String>,
+    /// The files the program was built from, as far as they are known.
+    pub files: Vec<PathBuf>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ShaderError {
+    pub fn preprocess(path: &Path, message: &str) -> ShaderError {
+        ShaderError {
+            kind: ShaderErrorKind::Preprocess,
+            path: path.to_path_buf(),
+            defines: Vec::new(),
+            files: vec![path.to_path_buf()],
+            diagnostics: message.lines().map(|line| Diagnostic {
+                file: None,
+                line: None,
+                message: line.to_owned(),
+            }).collect(),
+        }
+    }
+
+    /// `files` maps the source string numbers in the log to file paths.
+    pub fn from_log(kind: ShaderErrorKind, path: &Path, log: &str, files: &[PathBuf]) -> ShaderError {
+        ShaderError {
+            kind,
+            path: path.to_path_buf(),
+            defines: Vec::new(),
+            files: files.to_vec(),
+            diagnostics: parse_log(log, files),
+        }
+    }
+
+    pub fn with_defines(mut self, defines: &[String]) -> ShaderError {
+        self.defines = defines.to_vec();
+        self
+    }
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ShaderErrorKind::Preprocess => write!(f, "Failed to preprocess {}", self.path.display())?,
+            ShaderErrorKind::Compile(stage) => {
+                write!(f, "Failed to compile {} shader {}", stage.name(), self.path.display())?
+            }
+            ShaderErrorKind::Link => write!(f, "Failed to link {}", self.path.display())?,
+        }
+        if !self.defines.is_empty() {
+            write!(f, " with {}", self.defines.join(", "))?;
+        }
+        for diagnostic in &self.diagnostics {
+            write!(f, "\n    ")?;
+            match (&diagnostic.file, diagnostic.line) {
+                (Some(file), Some(line)) => write!(f, "{}:{}: ", file.display(), line)?,
+                (None, Some(line)) => write!(f, "line {}: ", line)?,
+                _ => {}
+            }
+            write!(f, "{}", diagnostic.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<ShaderError> for String {
+    fn from(error: ShaderError) -> String {
+        error.to_string()
+    }
+}
+
+// Split a leading decimal number off `s`.
+fn split_number(s: &str) -> Option<(usize, &str)> {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let number = s[..end].parse().ok()?;
+    Some((number, &s[end..]))
+}
+
+// Mesa: "0:12(8): error: `x' undeclared"
+fn parse_mesa(line: &str) -> Option<(usize, usize, String)> {
+    let (source, rest) = split_number(line)?;
+    let (line_number, rest) = split_number(rest.strip_prefix(':')?)?;
+    let (_, rest) = split_number(rest.strip_prefix('(')?)?;
+    let message = rest.strip_prefix("): ")?;
+    Some((source, line_number, message.to_owned()))
+}
+
+// NVIDIA: "0(12) : error C1008: undefined variable "x""
+fn parse_nvidia(line: &str) -> Option<(usize, usize, String)> {
+    let (source, rest) = split_number(line)?;
+    let (line_number, rest) = split_number(rest.strip_prefix('(')?)?;
+    let message = rest.strip_prefix(") : ")?;
+    Some((source, line_number, message.to_owned()))
+}
+
+// AMD and others: "ERROR: 0:12: 'x' : undeclared identifier"
+fn parse_amd(line: &str) -> Option<(usize, usize, String)> {
+    let (severity, rest) = if let Some(rest) = line.strip_prefix("ERROR: ") {
+        ("error", rest)
+    } else {
+        ("warning", line.strip_prefix("WARNING: ")?)
+    };
+    let (source, rest) = split_number(rest)?;
+    let (line_number, rest) = split_number(rest.strip_prefix(':')?)?;
+    let message = rest.strip_prefix(": ")?;
+    Some((source, line_number, format!("{}: {}", severity, message)))
+}
+
+/// Parse a compile or link info log. Lines without a recognised location
+/// are kept as diagnostics without a file or line.
+pub fn parse_log(log: &str, files: &[PathBuf]) -> Vec<Diagnostic> {
+    log.lines()
+       .map(|line| line.trim())
+       .filter(|line| !line.is_empty())
+       .map(|line| {
+           match parse_mesa(line).or_else(|| parse_nvidia(line)).or_else(|| parse_amd(line)) {
+               Some((source, line_number, message)) => Diagnostic {
+                   file: files.get(source).cloned(),
+                   line: Some(line_number),
+                   message,
+               },
+               None => Diagnostic {
+                   file: None,
+                   line: None,
+                   message: line.to_owned(),
+               },
+           }
+       })
+       .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_log;
+    use preprocessor;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    fn files() -> Vec<PathBuf> {
+        vec![PathBuf::from("res/quad_bench.glsl"), PathBuf::from("res/shared.glsl")]
+    }
+
+    fn located(log: &str) -> Vec<(Option<PathBuf>, Option<usize>, String)> {
+        parse_log(log, &files()).into_iter().map(|d| (d.file, d.line, d.message)).collect()
+    }
+
+    #[test]
+    fn mesa_log() {
+        let log = "0:12(8): error: `x' undeclared\n\
+                   1:40(3): warning: unused variable\n";
+        assert_eq!(located(log), vec![
+            (Some(PathBuf::from("res/quad_bench.glsl")), Some(12), "error: `x' undeclared".to_owned()),
+            (Some(PathBuf::from("res/shared.glsl")), Some(40), "warning: unused variable".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn nvidia_log() {
+        let log = "1(7) : error C1008: undefined variable \"x\"\n\
+                   0(2) : warning C7022: unrecognized profile specifier\n";
+        assert_eq!(located(log), vec![
+            (Some(PathBuf::from("res/shared.glsl")), Some(7), "error C1008: undefined variable \"x\"".to_owned()),
+            (Some(PathBuf::from("res/quad_bench.glsl")), Some(2), "warning C7022: unrecognized profile specifier".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn amd_log() {
+        let log = "ERROR: 0:12: 'x' : undeclared identifier\n\
+                   WARNING: 1:3: extension not supported\n\
+                   ERROR: 1 compilation errors.  No code generated.\n";
+        assert_eq!(located(log), vec![
+            (Some(PathBuf::from("res/quad_bench.glsl")), Some(12), "error: 'x' : undeclared identifier".to_owned()),
+            (Some(PathBuf::from("res/shared.glsl")), Some(3), "warning: extension not supported".to_owned()),
+            (None, None, "ERROR: 1 compilation errors.  No code generated.".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn unknown_lines_and_sources() {
+        let log = "\n  error: linking failed  \n7:1(1): error: bad source\n";
+        assert_eq!(located(log), vec![
+            (None, None, "error: linking failed".to_owned()),
+            (None, Some(1), "error: bad source".to_owned()),
+        ]);
+    }
+
+    // Number the lines of preprocessed source the way a GLSL 1.50 compiler
+    // does, returning the source string and line of the first line that
+    // contains `needle`.
+    fn compiler_location(text: &str, needle: &str) -> (usize, usize) {
+        let (mut source, mut line) = (0, 1);
+        for text_line in text.lines() {
+            let mut words = text_line.split_whitespace();
+            if words.next() == Some("#line") {
+                line = words.next().unwrap().parse::<usize>().unwrap() + 1;
+                source = words.next().unwrap().parse().unwrap();
+                continue;
+            }
+            if text_line.contains(needle) {
+                return (source, line);
+            }
+            line += 1;
+        }
+        panic!("{} not found", needle);
+    }
+
+    #[test]
+    fn locations_map_back_through_line_directives() {
+        let dir = env::temp_dir().join(format!("gl_sandpit_shader_error_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("main.glsl"), "#include \"shared.glsl\"\n\nvoid main() {\n    main_error;\n}\n").unwrap();
+        fs::write(dir.join("shared.glsl"), "#pragma once\nfloat a;\nshared_error;\n").unwrap();
+        let source = preprocessor::preprocess(&dir.join("main.glsl"));
+        let _ = fs::remove_dir_all(&dir);
+        let source = source.unwrap();
+
+        let (main_source, main_line) = compiler_location(&source.text, "main_error");
+        let (shared_source, shared_line) = compiler_location(&source.text, "shared_error");
+        let log = format!("{}:{}(5): error: main\n{}:{}(1): error: shared\n",
+                          main_source, main_line, shared_source, shared_line);
+        let diagnostics = parse_log(&log, &source.files);
+
+        assert_eq!(diagnostics[0].file, Some(dir.join("main.glsl")));
+        assert_eq!(diagnostics[0].line, Some(4));
+        assert_eq!(diagnostics[1].file, Some(dir.join("shared.glsl")));
+        assert_eq!(diagnostics[1].line, Some(3));
+    }
+}
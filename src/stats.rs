@@ -0,0 +1,310 @@
+use gpu_profiler::GpuFrameTimings;
+use std::f64;
+use std::fmt;
+
+/// CPU side timings for a single frame, in nanoseconds.
+#[derive(Debug, Copy, Clone)]
+pub struct FrameTiming {
+    pub cpu_ns: u64,        // begin_frame() to end_frame(), i.e. GL command submission.
+    pub swap_ns: u64,       // Time spent in swap_buffers().
+    pub total_ns: u64,      // Whole frame, including the swap.
+}
+
+/// Summary statistics of a set of samples. All values are in milliseconds.
+#[derive(Debug, Copy, Clone)]
+pub struct Summary {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub stddev: f64,
+    // Samples outside Tukey's fences (more than 1.5 IQR outside the quartiles).
+    pub outliers: usize,
+}
+
+impl Summary {
+    pub fn from_samples(samples: &[f64]) -> Summary {
+        if samples.is_empty() {
+            return Summary {
+                count: 0,
+                min: 0.0,
+                max: 0.0,
+                mean: 0.0,
+                median: 0.0,
+                p95: 0.0,
+                p99: 0.0,
+                stddev: 0.0,
+                outliers: 0,
+            };
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let count = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / count as f64;
+        let variance = if count > 1 {
+            sorted.iter().map(|s| (s - mean) * (s - mean)).sum::<f64>() / (count - 1) as f64
+        } else {
+            0.0
+        };
+
+        let q1 = percentile(&sorted, 25.0);
+        let q3 = percentile(&sorted, 75.0);
+        let iqr = q3 - q1;
+        let low_fence = q1 - 1.5 * iqr;
+        let high_fence = q3 + 1.5 * iqr;
+        let outliers = sorted.iter().filter(|&&s| s < low_fence || s > high_fence).count();
+
+        Summary {
+            count,
+            min: sorted[0],
+            max: sorted[count - 1],
+            mean,
+            median: percentile(&sorted, 50.0),
+            p95: percentile(&sorted, 95.0),
+            p99: percentile(&sorted, 99.0),
+            stddev: variance.sqrt(),
+            outliers,
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "min={:.3}ms max={:.3}ms mean={:.3}ms median={:.3}ms p95={:.3}ms p99={:.3}ms stddev={:.3}ms outliers={}",
+               self.min,
+               self.max,
+               self.mean,
+               self.median,
+               self.p95,
+               self.p99,
+               self.stddev,
+               self.outliers)
+    }
+}
+
+/// Linearly interpolated percentile of an already sorted slice.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+fn summarize_ns<I>(samples: I) -> Summary where I: Iterator<Item=u64> {
+    let samples: Vec<f64> = samples.map(|ns| ns as f64 / 1000000.0).collect();
+    Summary::from_samples(&samples)
+}
+
+/// Per-frame timings collected over a run, excluding warm-up frames.
+pub struct FrameStats {
+    pub frames: Vec<FrameTiming>,
+    pub gpu_frames: Vec<u64>,
+    pub gpu_scopes: Vec<(String, Vec<u64>)>,
+}
+
+impl FrameStats {
+    pub fn new() -> FrameStats {
+        FrameStats {
+            frames: Vec::new(),
+            gpu_frames: Vec::new(),
+            gpu_scopes: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, timing: FrameTiming) {
+        self.frames.push(timing);
+    }
+
+    pub fn push_gpu(&mut self, timings: GpuFrameTimings) {
+        self.gpu_frames.push(timings.total_ns);
+        for (name, ns) in timings.scopes {
+            match self.gpu_scopes.iter().position(|(n, _)| *n == name) {
+                Some(index) => self.gpu_scopes[index].1.push(ns),
+                None => self.gpu_scopes.push((name, vec![ns])),
+            }
+        }
+    }
+
+    fn summarize<F>(&self, f: F) -> Summary where F: Fn(&FrameTiming) -> u64 {
+        summarize_ns(self.frames.iter().map(f))
+    }
+
+    pub fn cpu(&self) -> Summary {
+        self.summarize(|t| t.cpu_ns)
+    }
+
+    pub fn swap(&self) -> Summary {
+        self.summarize(|t| t.swap_ns)
+    }
+
+    pub fn total(&self) -> Summary {
+        self.summarize(|t| t.total_ns)
+    }
+
+    pub fn gpu(&self) -> Summary {
+        summarize_ns(self.gpu_frames.iter().cloned())
+    }
+
+    pub fn gpu_scopes(&self) -> Vec<(&str, Summary)> {
+        self.gpu_scopes.iter().map(|(name, samples)| {
+            (name.as_str(), summarize_ns(samples.iter().cloned()))
+        }).collect()
+    }
+}
+
+/// Two-sided p-value of the Mann-Whitney U test that `a` and `b` come
+/// from the same distribution, using the normal approximation with a
+/// correction for ties. Frame times are rarely normally distributed, so
+/// this rank based test is more trustworthy than a t-test.
+pub fn mann_whitney_p(a: &[f64], b: &[f64]) -> f64 {
+    let n1 = a.len() as f64;
+    let n2 = b.len() as f64;
+    if a.is_empty() || b.is_empty() {
+        return 1.0;
+    }
+
+    let (u, tie_term) = mann_whitney_u(a, b);
+    let n = n1 + n2;
+    let mean = n1 * n2 / 2.0;
+    let variance = n1 * n2 / 12.0 * ((n + 1.0) - tie_term / (n * (n - 1.0)));
+    if variance <= 0.0 {
+        return 1.0;
+    }
+
+    let z = (u - mean).abs() / variance.sqrt();
+    (2.0 * (1.0 - normal_cdf(z))).min(1.0)
+}
+
+/// The U statistic of `a`, and the sum of t^3 - t over each run of t tied
+/// values, which the variance is corrected by.
+fn mann_whitney_u(a: &[f64], b: &[f64]) -> (f64, f64) {
+    let n1 = a.len() as f64;
+    let mut all: Vec<(f64, bool)> = a.iter().map(|&s| (s, true))
+                                     .chain(b.iter().map(|&s| (s, false)))
+                                     .collect();
+    all.sort_by(|x, y| x.0.partial_cmp(&y.0).unwrap());
+
+    // Assign ranks, averaging over runs of tied values.
+    let mut rank_sum_a = 0.0;
+    let mut tie_term = 0.0;
+    let mut i = 0;
+    while i < all.len() {
+        let mut j = i;
+        while j + 1 < all.len() && all[j + 1].0 == all[i].0 {
+            j += 1;
+        }
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        let ties = (j - i + 1) as f64;
+        tie_term += ties * ties * ties - ties;
+        rank_sum_a += all[i..j + 1].iter().filter(|s| s.1).count() as f64 * rank;
+        i = j + 1;
+    }
+
+    (rank_sum_a - n1 * (n1 + 1.0) / 2.0, tie_term)
+}
+
+fn normal_cdf(x: f64) -> f64 {
+    0.5 * (1.0 + erf(x / f64::consts::SQRT_2))
+}
+
+// Abramowitz and Stegun formula 7.1.26, accurate to about 1.5e-7.
+fn erf(x: f64) -> f64 {
+    let sign = if x < 0.0 { -1.0 } else { 1.0 };
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let y = 1.0 - (((((1.061405429 * t - 1.453152027) * t) + 1.421413741) * t - 0.284496736) * t + 0.254829592) * t * (-x * x).exp();
+    sign * y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mann_whitney_p, mann_whitney_u, percentile, Summary};
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "{} is not within {} of {}", actual, tolerance, expected);
+    }
+
+    #[test]
+    fn percentiles_interpolate_between_samples() {
+        let sorted = [15.0, 20.0, 35.0, 40.0, 50.0];
+        assert_close(percentile(&sorted, 0.0), 15.0, 1e-9);
+        assert_close(percentile(&sorted, 25.0), 20.0, 1e-9);
+        assert_close(percentile(&sorted, 50.0), 35.0, 1e-9);
+        assert_close(percentile(&sorted, 95.0), 48.0, 1e-9);
+        assert_close(percentile(&sorted, 100.0), 50.0, 1e-9);
+
+        let even = [1.0, 2.0, 3.0, 4.0];
+        assert_close(percentile(&even, 25.0), 1.75, 1e-9);
+        assert_close(percentile(&even, 50.0), 2.5, 1e-9);
+    }
+
+    #[test]
+    fn summary_of_unsorted_samples() {
+        let summary = Summary::from_samples(&[9.0, 2.0, 5.0, 4.0, 4.0, 7.0, 4.0, 5.0]);
+        assert_eq!(summary.count, 8);
+        assert_close(summary.min, 2.0, 1e-9);
+        assert_close(summary.max, 9.0, 1e-9);
+        assert_close(summary.mean, 5.0, 1e-9);
+        assert_close(summary.median, 4.5, 1e-9);
+        // Sample standard deviation, sqrt(32 / 7).
+        assert_close(summary.stddev, 2.13809, 1e-5);
+        // 9 is above the upper fence of 5.5 + 1.5 * 1.5.
+        assert_eq!(summary.outliers, 1);
+    }
+
+    #[test]
+    fn summary_of_no_samples() {
+        let summary = Summary::from_samples(&[]);
+        assert_eq!(summary.count, 0);
+        assert_eq!(summary.median, 0.0);
+        assert_eq!(summary.outliers, 0);
+    }
+
+    #[test]
+    fn outliers_are_outside_tukey_fences() {
+        // Quartiles 3.25 and 7.75, so the fences are at -3.5 and 14.5.
+        let samples = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 100.0];
+        assert_eq!(Summary::from_samples(&samples).outliers, 1);
+
+        let samples = [-50.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 14.0, 100.0];
+        assert_eq!(Summary::from_samples(&samples).outliers, 2);
+    }
+
+    #[test]
+    fn mann_whitney_textbook_example() {
+        // Ranks of a are 3, 5, 7, 8 and 9, so U = 32 - 15 = 17, and
+        // U for b is 20 - 17 = 3.
+        let a = [19.0, 22.0, 16.0, 29.0, 24.0];
+        let b = [20.0, 11.0, 17.0, 12.0];
+        assert_close(mann_whitney_u(&a, &b).0, 17.0, 1e-9);
+        assert_close(mann_whitney_u(&b, &a).0, 3.0, 1e-9);
+        // Normal approximation: z = 7 / sqrt(50 / 3).
+        assert_close(mann_whitney_p(&a, &b), 0.08641, 1e-4);
+        assert_close(mann_whitney_p(&b, &a), 0.08641, 1e-4);
+    }
+
+    #[test]
+    fn mann_whitney_ties() {
+        // 2.0 appears three times, sharing rank 3, and 3.0 twice,
+        // sharing rank 5.5.
+        let a = [1.0, 2.0, 2.0, 3.0];
+        let b = [2.0, 3.0, 4.0, 5.0];
+        let (u, tie_term) = mann_whitney_u(&a, &b);
+        assert_close(u, 1.0 + 3.0 + 3.0 + 5.5 - 10.0, 1e-9);
+        assert_close(tie_term, 24.0 + 6.0, 1e-9);
+    }
+
+    #[test]
+    fn mann_whitney_degenerate_samples() {
+        assert_eq!(mann_whitney_p(&[], &[1.0]), 1.0);
+        assert_eq!(mann_whitney_p(&[2.0, 2.0], &[2.0, 2.0]), 1.0);
+        assert!(mann_whitney_p(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0],
+                               &[11.0, 12.0, 13.0, 14.0, 15.0, 16.0, 17.0, 18.0]) < 0.01);
+    }
+}
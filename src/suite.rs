@@ -0,0 +1,186 @@
+use backend::Backend;
+use gfx::GfxContext;
+use options::Options;
+use registry::AppRegistry;
+use report::{Environment, Report};
+use runner;
+use std::env;
+use std::fs;
+use std::process::{Command, Stdio};
+use std::slice;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Expand suite arguments into a list of app specs. Arguments starting
+/// with `@` name a suite file containing one entry per line, where blank
+/// lines and lines starting with `#` are ignored. Entries may be globs.
+fn collect_entries(registry: &AppRegistry, args: &[String]) -> Result<Vec<String>, String> {
+    let mut entries = Vec::new();
+
+    for arg in args {
+        let lines = if let Some(path) = arg.strip_prefix('@') {
+            let text = fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path, e))?;
+            text.lines()
+                .map(|line| line.trim().to_owned())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .collect()
+        } else {
+            vec![arg.clone()]
+        };
+
+        for line in lines {
+            let names = registry.expand(&line);
+            if names.is_empty() {
+                return Err(format!("'{}' does not match any app", line));
+            }
+            entries.extend(names);
+        }
+    }
+
+    // Catch typos before spending time running anything.
+    for entry in &entries {
+        registry.resolve(slice::from_ref(entry))?;
+    }
+
+    Ok(entries)
+}
+
+/// Run one app in a child process of this executable, and read back
+/// the JSON report it writes.
+fn run_isolated(entry: &str, index: usize, options: &Options) -> Result<Report, String> {
+    let report_path = env::temp_dir().join(format!("gl_sandpit_{}_{}.json", ::std::process::id(), index));
+
+    let exe = env::current_exe().map_err(|e| e.to_string())?;
+    let mut command = Command::new(exe);
+    if options.headless {
+        command.arg("--headless");
+    }
+    command.arg("--size").arg(format!("{}x{}", options.width, options.height));
+    command.arg("--warmup").arg(options.warmup.to_string());
+    command.arg("--upload").arg(options.upload.name());
+    if let Some(frames) = options.frames {
+        command.arg("--frames").arg(frames.to_string());
+    }
+    command.arg("--no-input");
+    command.arg("--report").arg("json");
+    command.arg("--output").arg(&report_path);
+    command.arg(entry);
+    command.stdout(Stdio::null());
+
+    let mut child = command.spawn().map_err(|e| format!("failed to start: {}", e))?;
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+        if let Some(timeout) = options.timeout {
+            if start.elapsed() > Duration::from_secs(timeout) {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {}s", timeout));
+            }
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    if !status.success() {
+        let _ = fs::remove_file(&report_path);
+        return Err(format!("exited with {}", status));
+    }
+
+    let report = Report::load(&report_path);
+    let _ = fs::remove_file(&report_path);
+    report
+}
+
+/// Run every app in the suite in sequence and produce one combined report.
+/// Returns the process exit code.
+pub fn run_suite(registry: &AppRegistry, options: &Options) -> i32 {
+    // Nobody is there to close a test app's window, so they draw a fixed
+    // number of frames like benchmarks do.
+    let options = &Options {
+        interactive: false,
+        ..options.clone()
+    };
+
+    let entries = match collect_entries(registry, &options.app_args) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    let mut report = Report::new(Environment::unknown());
+    let mut failures = 0;
+
+    if options.isolate {
+        for (index, entry) in entries.iter().enumerate() {
+            match run_isolated(entry, index, options) {
+                Ok(app_report) => {
+                    if report.records.is_empty() {
+                        report.environment = app_report.environment;
+                    }
+                    for record in app_report.records {
+                        if options.report.is_none() {
+                            runner::print_record(&record, &report.environment);
+                        }
+                        report.records.push(record);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}: {}", entry, e);
+                    failures += 1;
+                }
+            }
+        }
+    } else {
+        let backend = if options.headless {
+            Backend::new_headless(options.width, options.height)
+        } else {
+            Backend::new_window("suite", options.width, options.height)
+        };
+        let backend = match backend {
+            Ok(backend) => backend,
+            Err(e) => {
+                eprintln!("Failed to create GL context: {}", e);
+                return 1;
+            }
+        };
+        report.environment = Environment::query();
+
+        for entry in &entries {
+            let spec = registry.resolve(slice::from_ref(entry)).unwrap();
+
+            // Each app gets a fresh context, which resets GL state and
+            // releases the previous app's resources when dropped.
+            let mut gfx = GfxContext::new(backend.create_window_proxy(), options.upload);
+            match runner::run_app(&backend, &mut gfx, &spec, options) {
+                Ok(record) => {
+                    if options.report.is_none() {
+                        runner::print_record(&record, &report.environment);
+                    }
+                    report.records.push(record);
+                }
+                Err(e) => {
+                    eprintln!("{}: {}", entry, e);
+                    failures += 1;
+                }
+            }
+        }
+    }
+
+    if let Some(format) = options.report {
+        if let Err(e) = report.write(format, options.output.as_deref()) {
+            eprintln!("Failed to write report: {}", e);
+            return 1;
+        }
+    }
+
+    if failures > 0 {
+        eprintln!("{} of {} apps failed", failures, entries.len());
+        1
+    } else {
+        0
+    }
+}
@@ -0,0 +1,120 @@
+use gleam::gl;
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+    /// Linear filtering between mipmap levels, which are generated when
+    /// the texture is created.
+    Trilinear,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextureWrap {
+    Clamp,
+    Repeat,
+    MirroredRepeat,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextureOptions {
+    pub filter: TextureFilter,
+    pub wrap: TextureWrap,
+}
+
+impl Default for TextureOptions {
+    fn default() -> TextureOptions {
+        TextureOptions {
+            filter: TextureFilter::Linear,
+            wrap: TextureWrap::Clamp,
+        }
+    }
+}
+
+/// An RGBA8 color texture. Rows are stored top down, so v = 0 is the top
+/// of the image, matching the top left origin of `uTransform`.
+pub struct Texture {
+    pub id: gl::GLuint,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Texture {
+    /// The contents are undefined if there are no `pixels`.
+    pub fn new(width: u32,
+               height: u32,
+               pixels: Option<&[u8]>,
+               options: TextureOptions) -> Result<Texture, String> {
+        if let Some(pixels) = pixels {
+            if pixels.len() != width as usize * height as usize * 4 {
+                return Err(format!("{}x{} RGBA8 texture needs {} bytes, but has {}",
+                                   width,
+                                   height,
+                                   width as usize * height as usize * 4,
+                                   pixels.len()));
+            }
+        }
+        let max_size = gl::get_integer_v(gl::MAX_TEXTURE_SIZE) as u32;
+        if width == 0 || height == 0 || width > max_size || height > max_size {
+            return Err(format!("{}x{} texture is outside the supported sizes (1 to {})",
+                               width,
+                               height,
+                               max_size));
+        }
+
+        let (min_filter, mag_filter) = match options.filter {
+            TextureFilter::Nearest => (gl::NEAREST, gl::NEAREST),
+            TextureFilter::Linear => (gl::LINEAR, gl::LINEAR),
+            TextureFilter::Trilinear => (gl::LINEAR_MIPMAP_LINEAR, gl::LINEAR),
+        };
+        let wrap = match options.wrap {
+            TextureWrap::Clamp => gl::CLAMP_TO_EDGE,
+            TextureWrap::Repeat => gl::REPEAT,
+            TextureWrap::MirroredRepeat => gl::MIRRORED_REPEAT,
+        };
+
+        let id = gl::gen_textures(1)[0];
+        gl::bind_texture(gl::TEXTURE_2D, id);
+        gl::tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter as gl::GLint);
+        gl::tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, mag_filter as gl::GLint);
+        gl::tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, wrap as gl::GLint);
+        gl::tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, wrap as gl::GLint);
+        gl::tex_image_2d(gl::TEXTURE_2D,
+                         0,
+                         gl::RGBA8 as gl::GLint,
+                         width as gl::GLint,
+                         height as gl::GLint,
+                         0,
+                         gl::RGBA,
+                         gl::UNSIGNED_BYTE,
+                         pixels);
+        if options.filter == TextureFilter::Trilinear {
+            gl::generate_mipmap(gl::TEXTURE_2D);
+        }
+        gl::bind_texture(gl::TEXTURE_2D, 0);
+
+        Ok(Texture {
+            id,
+            width,
+            height,
+        })
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        gl::delete_textures(&[self.id]);
+    }
+}
+
+/// Multiply the color channels of RGBA8 pixels by their alpha.
+pub fn premultiply(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_mut(4) {
+        let alpha = pixel[3] as u32;
+        for channel in &mut pixel[..3] {
+            *channel = ((*channel as u32 * alpha + 127) / 255) as u8;
+        }
+    }
+}
@@ -10,10 +10,10 @@ pub struct ColorF {
 impl ColorF {
     pub fn new(r: f32, g: f32, b: f32, a: f32) -> ColorF {
         ColorF {
-            r: r,
-            g: g,
-            b: b,
-            a: a,
+            r,
+            g,
+            b,
+            a,
         }
     }
 
@@ -22,11 +22,44 @@ impl ColorF {
             r: self.r,
             g: self.g,
             b: self.b,
-            a: a,
+            a,
         }
     }
 }
 
+/// A rectangle in pixels, with the origin at the top left like `uTransform`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn from_size(width: u32, height: u32) -> Rect {
+        Rect::new(0, 0, width as i32, height as i32)
+    }
+
+    /// The overlap of two rectangles, which has no area if they don't overlap.
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let x0 = self.x.max(other.x);
+        let y0 = self.y.max(other.y);
+        let x1 = (self.x + self.width).min(other.x + other.width);
+        let y1 = (self.y + self.height).min(other.y + other.height);
+        Rect::new(x0, y0, (x1 - x0).max(0), (y1 - y0).max(0))
+    }
+}
+
 #[allow(dead_code)]
 pub const RED: ColorF = ColorF { r: 1.0, g: 0.0, b: 0.0, a: 1.0 };
 #[allow(dead_code)]
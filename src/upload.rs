@@ -0,0 +1,325 @@
+use gleam::gl;
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use time::precise_time_ns;
+
+// Buffer storage is GL 4.4 / ARB_buffer_storage, which the GL 3.3 bindings
+// don't include.
+const MAP_PERSISTENT_BIT: gl::GLbitfield = 0x0040;
+const MAP_COHERENT_BIT: gl::GLbitfield = 0x0080;
+
+type BufferStorageFn = extern "system" fn(gl::GLenum, gl::GLsizeiptr, *const c_void, gl::GLbitfield);
+
+static BUFFER_STORAGE: AtomicUsize = AtomicUsize::new(0);
+
+/// Load the buffer storage entry point. Called alongside `gl::load_with`.
+pub fn load_with<F>(mut get_proc_address: F) where F: FnMut(&str) -> *const c_void {
+    BUFFER_STORAGE.store(get_proc_address("glBufferStorage") as usize, Ordering::Relaxed);
+}
+
+fn buffer_storage() -> Option<BufferStorageFn> {
+    let buffer_storage = BUFFER_STORAGE.load(Ordering::Relaxed);
+    if buffer_storage == 0 {
+        return None;
+    }
+    Some(unsafe { mem::transmute::<usize, BufferStorageFn>(buffer_storage) })
+}
+
+// Number of frames whose uploads may be in flight at once. A segment is
+// reused this many frames after it was written.
+const FRAMES_IN_FLIGHT: usize = 3;
+
+// Offsets within a segment keep this alignment, which suits every texel
+// and buffer format that is uploaded.
+const UPLOAD_ALIGNMENT: usize = 64;
+
+const INITIAL_SEGMENT_SIZE: usize = 64 * 1024;
+
+// How long to wait for the GPU to finish with a segment before giving up,
+// e.g. because it hung.
+const FENCE_TIMEOUT_NS: u64 = 10000000000;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum UploadMethod {
+    /// Upload straight from client memory with tex_sub_image_2d and
+    /// buffer_sub_data, letting the driver deal with synchronization.
+    Direct,
+    /// Write into a ring of pixel buffer objects, mapped unsynchronized.
+    Pbo,
+    /// Write into a ring of persistently mapped buffers. Needs
+    /// ARB_buffer_storage, and falls back to `Pbo` without it.
+    Persistent,
+}
+
+impl UploadMethod {
+    pub fn parse(name: &str) -> Option<UploadMethod> {
+        match name {
+            "direct" => Some(UploadMethod::Direct),
+            "pbo" => Some(UploadMethod::Pbo),
+            "persistent" => Some(UploadMethod::Persistent),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            UploadMethod::Direct => "direct",
+            UploadMethod::Pbo => "pbo",
+            UploadMethod::Persistent => "persistent",
+        }
+    }
+}
+
+/// Data uploaded, and how often the CPU had to wait for the GPU to finish
+/// with a segment of the ring before writing to it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct UploadStats {
+    pub method: UploadMethod,
+    pub bytes: u64,
+    pub waits: usize,
+    pub wait_ns: u64,
+}
+
+// The part of the ring written during one frame.
+struct Segment {
+    buffer: gl::GLuint,
+    size: usize,
+    used: usize,
+    // Signalled when the GPU has finished with the frame that last used
+    // this segment.
+    fence: gl::GLsync,
+    // Where a persistent segment is mapped.
+    mapping: *mut u8,
+}
+
+/// Streams per-frame data to the GPU through a ring of buffers, one segment
+/// per frame in flight. Fences make sure a segment isn't overwritten while
+/// the GPU may still be reading it.
+pub struct Uploader {
+    method: UploadMethod,
+    segments: Vec<Segment>,
+    current: usize,
+    pub stats: UploadStats,
+}
+
+impl Uploader {
+    /// Requires a current GL context.
+    pub fn new(method: UploadMethod) -> Uploader {
+        let method = match method {
+            UploadMethod::Persistent if buffer_storage().is_none() => {
+                eprintln!("Persistently mapped buffers are unsupported, using pixel buffer objects");
+                UploadMethod::Pbo
+            }
+            method => method,
+        };
+
+        let mut uploader = Uploader {
+            method,
+            segments: Vec::new(),
+            current: 0,
+            stats: UploadStats {
+                method,
+                bytes: 0,
+                waits: 0,
+                wait_ns: 0,
+            },
+        };
+        if method != UploadMethod::Direct {
+            for _ in 0..FRAMES_IN_FLIGHT {
+                let segment = uploader.create_segment(INITIAL_SEGMENT_SIZE);
+                uploader.segments.push(segment);
+            }
+        }
+        uploader
+    }
+
+    fn create_segment(&self, size: usize) -> Segment {
+        let buffer = gl::gen_buffers(1)[0];
+        let mut mapping = ptr::null_mut();
+
+        gl::bind_buffer(gl::COPY_WRITE_BUFFER, buffer);
+        unsafe {
+            match buffer_storage() {
+                Some(buffer_storage) if self.method == UploadMethod::Persistent => {
+                    let flags = gl::MAP_WRITE_BIT | MAP_PERSISTENT_BIT | MAP_COHERENT_BIT;
+                    buffer_storage(gl::COPY_WRITE_BUFFER, size as gl::GLsizeiptr, ptr::null(), flags);
+                    mapping = gl::MapBufferRange(gl::COPY_WRITE_BUFFER,
+                                                 0,
+                                                 size as gl::GLsizeiptr,
+                                                 flags) as *mut u8;
+                }
+                _ => {
+                    gl::BufferData(gl::COPY_WRITE_BUFFER, size as gl::GLsizeiptr, ptr::null(), gl::STREAM_COPY);
+                }
+            }
+        }
+        gl::bind_buffer(gl::COPY_WRITE_BUFFER, 0);
+
+        Segment {
+            buffer,
+            size,
+            used: 0,
+            fence: ptr::null(),
+            mapping,
+        }
+    }
+
+    /// Move on to the next segment, waiting for the GPU to finish with it.
+    /// Fails if the GPU doesn't finish within `FENCE_TIMEOUT_NS`.
+    pub fn begin_frame(&mut self) -> Result<(), String> {
+        if self.segments.is_empty() {
+            return Ok(());
+        }
+        self.current = (self.current + 1) % self.segments.len();
+        let segment = &mut self.segments[self.current];
+        if !segment.fence.is_null() {
+            unsafe {
+                let mut status = gl::ClientWaitSync(segment.fence, 0, 0);
+                if status == gl::TIMEOUT_EXPIRED {
+                    let start = precise_time_ns();
+                    while status == gl::TIMEOUT_EXPIRED && precise_time_ns() - start < FENCE_TIMEOUT_NS {
+                        status = gl::ClientWaitSync(segment.fence, gl::SYNC_FLUSH_COMMANDS_BIT, 100000000);
+                    }
+                    self.stats.waits += 1;
+                    self.stats.wait_ns += precise_time_ns() - start;
+                }
+                match status {
+                    gl::TIMEOUT_EXPIRED => {
+                        return Err(format!("GPU did not finish with an upload segment within {}s",
+                                           FENCE_TIMEOUT_NS / 1000000000));
+                    }
+                    gl::WAIT_FAILED => return Err("waiting for an upload fence failed".to_owned()),
+                    _ => {}
+                }
+                gl::DeleteSync(segment.fence);
+            }
+            segment.fence = ptr::null();
+        }
+        segment.used = 0;
+        Ok(())
+    }
+
+    /// Fence the current segment once the frame's commands are issued.
+    pub fn end_frame(&mut self) {
+        if let Some(segment) = self.segments.get_mut(self.current) {
+            segment.fence = unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) };
+        }
+    }
+
+    // Copy `bytes` into the current segment, returning the buffer and offset
+    // they were written to.
+    fn write(&mut self, bytes: &[u8]) -> (gl::GLuint, usize) {
+        let offset = self.segments[self.current].used.next_multiple_of(UPLOAD_ALIGNMENT);
+        let offset = if offset + bytes.len() > self.segments[self.current].size {
+            // Commands already issued keep the old buffer alive, so it can be
+            // released straight away.
+            let size = (bytes.len().next_power_of_two()).max(self.segments[self.current].size * 2);
+            let segment = self.create_segment(size);
+            let old = mem::replace(&mut self.segments[self.current], segment);
+            old.release();
+            0
+        } else {
+            offset
+        };
+
+        let segment = &mut self.segments[self.current];
+        unsafe {
+            if segment.mapping.is_null() {
+                // Earlier frames using this segment are fenced, so the
+                // mapping doesn't need to synchronize.
+                gl::bind_buffer(gl::COPY_WRITE_BUFFER, segment.buffer);
+                let flags = gl::MAP_WRITE_BIT | gl::MAP_INVALIDATE_RANGE_BIT | gl::MAP_UNSYNCHRONIZED_BIT;
+                let mapping = gl::MapBufferRange(gl::COPY_WRITE_BUFFER,
+                                                 offset as gl::GLintptr,
+                                                 bytes.len() as gl::GLsizeiptr,
+                                                 flags) as *mut u8;
+                ptr::copy_nonoverlapping(bytes.as_ptr(), mapping, bytes.len());
+                gl::UnmapBuffer(gl::COPY_WRITE_BUFFER);
+                gl::bind_buffer(gl::COPY_WRITE_BUFFER, 0);
+            } else {
+                ptr::copy_nonoverlapping(bytes.as_ptr(), segment.mapping.add(offset), bytes.len());
+            }
+        }
+        segment.used = offset + bytes.len();
+        (segment.buffer, offset)
+    }
+
+    /// Replace the start of the buffer bound to `target` with `bytes`.
+    pub fn upload_buffer(&mut self, target: gl::GLenum, bytes: &[u8]) {
+        self.stats.bytes += bytes.len() as u64;
+        if self.method == UploadMethod::Direct {
+            gl::buffer_sub_data(target, 0, bytes);
+            return;
+        }
+
+        let (buffer, offset) = self.write(bytes);
+        gl::bind_buffer(gl::COPY_READ_BUFFER, buffer);
+        unsafe {
+            gl::CopyBufferSubData(gl::COPY_READ_BUFFER,
+                                  target,
+                                  offset as gl::GLintptr,
+                                  0,
+                                  bytes.len() as gl::GLsizeiptr);
+        }
+        gl::bind_buffer(gl::COPY_READ_BUFFER, 0);
+    }
+
+    /// Write `bytes` to a region of the texture bound to TEXTURE_2D.
+    pub fn upload_texture(&mut self,
+                          (x, y): (usize, usize),
+                          (width, height): (usize, usize),
+                          format: gl::GLenum,
+                          ty: gl::GLenum,
+                          bytes: &[u8]) {
+        self.stats.bytes += bytes.len() as u64;
+        if self.method == UploadMethod::Direct {
+            gl::tex_sub_image_2d(gl::TEXTURE_2D,
+                                 0,
+                                 x as gl::GLint,
+                                 y as gl::GLint,
+                                 width as gl::GLint,
+                                 height as gl::GLint,
+                                 format,
+                                 ty,
+                                 bytes);
+            return;
+        }
+
+        let (buffer, offset) = self.write(bytes);
+        gl::bind_buffer(gl::PIXEL_UNPACK_BUFFER, buffer);
+        unsafe {
+            gl::TexSubImage2D(gl::TEXTURE_2D,
+                              0,
+                              x as gl::GLint,
+                              y as gl::GLint,
+                              width as gl::GLint,
+                              height as gl::GLint,
+                              format,
+                              ty,
+                              offset as *const c_void);
+        }
+        gl::bind_buffer(gl::PIXEL_UNPACK_BUFFER, 0);
+    }
+}
+
+impl Segment {
+    fn release(self) {
+        unsafe {
+            if !self.fence.is_null() {
+                gl::DeleteSync(self.fence);
+            }
+        }
+        // Deleting a buffer unmaps it.
+        gl::delete_buffers(&[self.buffer]);
+    }
+}
+
+impl Drop for Uploader {
+    fn drop(&mut self) {
+        for segment in self.segments.drain(..) {
+            segment.release();
+        }
+    }
+}
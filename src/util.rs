@@ -1,17 +1,36 @@
-use std::path::PathBuf;
-use std::fs::File;
-use std::io::Read;
+use gleam::gl;
+use image;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::path::Path;
 
-pub fn load_text_file(path: &PathBuf) -> String {
-    let f = File::open(path);
-    match f {
-        Ok(mut f) => {
-            let mut s = String::new();
-            f.read_to_string(&mut s).unwrap();
-            s
-        }
-        Err(..) => {
-            panic!(format!("Unable to open file {}", path.display()));
-        }
-    }
+/// Save top-down RGBA8 pixels as a PNG.
+pub fn save_png(path: &Path, pixels: &[u8], width: u32, height: u32) -> Result<(), String> {
+    image::save_buffer(path, pixels, width, height, image::RGBA(8))
+        .map_err(|e| format!("unable to write {}: {}", path.display(), e))
+}
+
+/// Load a PNG, JPEG or GIF file as top-down RGBA8 pixels, returning (width, height, pixels).
+pub fn load_image(path: &Path) -> Result<(u32, u32, Vec<u8>), String> {
+    let image = image::open(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+    let rgba = image.to_rgba();
+    let (width, height) = rgba.dimensions();
+    Ok((width, height, rgba.into_raw()))
+}
+
+/// The (major, minor) version of the current GL context.
+pub fn gl_version() -> (u32, u32) {
+    let version = gl::get_string(gl::VERSION);
+    let mut numbers = version.split(|c: char| !c.is_ascii_digit())
+                             .filter_map(|n| n.parse().ok());
+    (numbers.next().unwrap_or(0), numbers.next().unwrap_or(0))
+}
+
+/// Whether the current GL context advertises an extension.
+pub fn has_extension(name: &str) -> bool {
+    let count = gl::get_integer_v(gl::NUM_EXTENSIONS);
+    (0..count as gl::GLuint).any(|i| unsafe {
+        let extension = gl::GetStringi(gl::EXTENSIONS, i);
+        !extension.is_null() && CStr::from_ptr(extension as *const c_char).to_bytes() == name.as_bytes()
+    })
 }
@@ -0,0 +1,316 @@
+use glutin::WindowProxy;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const POLL_INTERVAL_MS: u64 = 100;
+
+// Minimal inotify bindings. The watch is non-blocking and read on the
+// same interval as the polling fallback, so the thread can notice when
+// the watcher is dropped.
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::os::raw::{c_char, c_int, c_void};
+
+    pub const IN_NONBLOCK: c_int = 0o4000;
+    pub const IN_CLOEXEC: c_int = 0o2000000;
+    pub const IN_CLOSE_WRITE: u32 = 0x0008;
+    pub const IN_MOVED_TO: u32 = 0x0080;
+    pub const IN_CREATE: u32 = 0x0100;
+    pub const IN_ISDIR: u32 = 0x4000_0000;
+
+    // Size of the fixed part of struct inotify_event: wd, mask, cookie, len.
+    pub const EVENT_HEADER_SIZE: usize = 16;
+
+    extern "C" {
+        pub fn inotify_init1(flags: c_int) -> c_int;
+        pub fn inotify_add_watch(fd: c_int, path: *const c_char, mask: u32) -> c_int;
+        pub fn read(fd: c_int, buf: *mut c_void, count: usize) -> isize;
+        pub fn close(fd: c_int) -> c_int;
+    }
+}
+
+enum Source {
+    // inotify only watches single directories, so there is a watch for
+    // each directory in the tree, mapped to its path.
+    #[cfg(target_os = "linux")]
+    Inotify(i32, HashMap<i32, PathBuf>),
+    Poll(HashMap<PathBuf, SystemTime>),
+}
+
+#[cfg(target_os = "linux")]
+fn add_watches(fd: i32, dir: &Path, watches: &mut HashMap<i32, PathBuf>) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mask = inotify::IN_CLOSE_WRITE | inotify::IN_MOVED_TO | inotify::IN_CREATE;
+    for dir in directories(dir) {
+        let path = match CString::new(dir.as_os_str().as_bytes()) {
+            Ok(path) => path,
+            Err(..) => return false,
+        };
+        let wd = unsafe { inotify::inotify_add_watch(fd, path.as_ptr(), mask) };
+        if wd < 0 {
+            return false;
+        }
+        watches.insert(wd, dir);
+    }
+    true
+}
+
+impl Source {
+    #[cfg(target_os = "linux")]
+    fn inotify(dir: &Path) -> Option<Source> {
+        let fd = unsafe { inotify::inotify_init1(inotify::IN_NONBLOCK | inotify::IN_CLOEXEC) };
+        if fd < 0 {
+            return None;
+        }
+        let mut watches = HashMap::new();
+        if !add_watches(fd, dir, &mut watches) {
+            unsafe {
+                inotify::close(fd);
+            }
+            return None;
+        }
+        Some(Source::Inotify(fd, watches))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn inotify(_: &Path) -> Option<Source> {
+        None
+    }
+
+    fn poll(dir: &Path) -> Source {
+        Source::Poll(modification_times(dir))
+    }
+
+    fn changes(&mut self, dir: &Path) -> Vec<PathBuf> {
+        match *self {
+            #[cfg(target_os = "linux")]
+            Source::Inotify(fd, ref mut watches) => {
+                let mut changed = Vec::new();
+                let mut buffer = [0u8; 4096];
+                loop {
+                    let len = unsafe {
+                        inotify::read(fd, buffer.as_mut_ptr() as *mut _, buffer.len())
+                    };
+                    if len <= 0 {
+                        break;
+                    }
+
+                    let mut offset = 0;
+                    while offset + inotify::EVENT_HEADER_SIZE <= len as usize {
+                        let field = |index: usize| {
+                            let start = offset + index * 4;
+                            let mut bytes = [0u8; 4];
+                            bytes.copy_from_slice(&buffer[start..start + 4]);
+                            u32::from_ne_bytes(bytes)
+                        };
+                        let (wd, mask) = (field(0) as i32, field(1));
+                        let name_start = offset + inotify::EVENT_HEADER_SIZE;
+                        let name_end = name_start + field(3) as usize;
+
+                        // The name is nul padded.
+                        let name = &buffer[name_start..name_end];
+                        let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                        if let (false, Some(parent)) = (name.is_empty(), watches.get(&wd)) {
+                            let path = parent.join(String::from_utf8_lossy(name).as_ref());
+                            if mask & inotify::IN_ISDIR != 0 {
+                                // Watch new directories too. Files written
+                                // before the watch is added are missed.
+                                add_watches(fd, &path, watches);
+                            } else if mask & (inotify::IN_CLOSE_WRITE | inotify::IN_MOVED_TO) != 0 {
+                                changed.push(path);
+                            }
+                        }
+                        offset = name_end;
+                    }
+                }
+                changed
+            }
+            Source::Poll(ref mut times) => {
+                let current = modification_times(dir);
+                let changed = current.iter()
+                                     .filter(|&(path, time)| times.get(path) != Some(time))
+                                     .map(|(path, _)| path.clone())
+                                     .collect();
+                *times = current;
+                changed
+            }
+        }
+    }
+}
+
+impl Drop for Source {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        {
+            if let Source::Inotify(fd, _) = *self {
+                unsafe {
+                    inotify::close(fd);
+                }
+            }
+        }
+    }
+}
+
+/// `dir` and every directory below it.
+fn directories(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![dir.to_path_buf()];
+    let mut i = 0;
+    while i < dirs.len() {
+        if let Ok(entries) = fs::read_dir(&dirs[i]) {
+            let mut subdirs: Vec<PathBuf> = entries.filter_map(|e| e.ok())
+                                                   .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+                                                   .map(|e| e.path())
+                                                   .collect();
+            subdirs.sort();
+            dirs.extend(subdirs);
+        }
+        i += 1;
+    }
+    dirs
+}
+
+fn modification_times(dir: &Path) -> HashMap<PathBuf, SystemTime> {
+    let mut times = HashMap::new();
+    for dir in directories(dir) {
+        if let Ok(entries) = fs::read_dir(&dir) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                if let Ok(metadata) = entry.metadata() {
+                    if let (false, Ok(modified)) = (metadata.is_dir(), metadata.modified()) {
+                        times.insert(dir.join(entry.file_name()), modified);
+                    }
+                }
+            }
+        }
+    }
+    times
+}
+
+/// Watches the files in a directory tree from a background thread, waking
+/// the event loop through the window proxy when any of them change.
+pub struct FileWatcher {
+    rx: Receiver<PathBuf>,
+    running: Arc<AtomicBool>,
+}
+
+impl FileWatcher {
+    pub fn new(dir: &Path, window_proxy: Option<WindowProxy>) -> FileWatcher {
+        let (tx, rx) = channel();
+        let running = Arc::new(AtomicBool::new(true));
+
+        let dir = dir.to_path_buf();
+        let mut source = Source::inotify(&dir).unwrap_or_else(|| Source::poll(&dir));
+        let thread_running = running.clone();
+        thread::spawn(move || {
+            while thread_running.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+                let changed = source.changes(&dir);
+                if changed.is_empty() {
+                    continue;
+                }
+                for path in changed {
+                    if tx.send(path).is_err() {
+                        return;
+                    }
+                }
+                if let Some(ref window_proxy) = window_proxy {
+                    window_proxy.wakeup_event_loop();
+                }
+            }
+        });
+
+        FileWatcher {
+            rx,
+            running,
+        }
+    }
+
+    /// Files that changed since the last call, without duplicates.
+    pub fn changed_files(&self) -> Vec<PathBuf> {
+        let mut changed: Vec<PathBuf> = self.rx.try_iter().collect();
+        changed.sort();
+        changed.dedup();
+        changed
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileWatcher, Source};
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // Wait until every file in `paths` has been reported.
+    fn wait_for(watcher: &FileWatcher, paths: &[PathBuf]) -> bool {
+        let mut changed = Vec::new();
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            changed.extend(watcher.changed_files());
+            if paths.iter().all(|path| changed.contains(path)) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    #[test]
+    fn changes_in_subdirectories_are_reported() {
+        let dir = env::temp_dir().join(format!("gl_sandpit_watcher_{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("lib/nested")).unwrap();
+
+        let watcher = FileWatcher::new(&dir, None);
+        thread::sleep(Duration::from_millis(200));
+
+        let top = dir.join("top.glsl");
+        fs::write(&top, "1").unwrap();
+        let nested = dir.join("lib/nested/nested.glsl");
+        fs::write(&nested, "1").unwrap();
+        let found = wait_for(&watcher, &[top, nested]);
+
+        // Directories created after the watcher started are watched too.
+        fs::create_dir(dir.join("new")).unwrap();
+        thread::sleep(Duration::from_millis(300));
+        let new = dir.join("new/new.glsl");
+        fs::write(&new, "1").unwrap();
+        let found_new = wait_for(&watcher, &[new]);
+
+        let _ = fs::remove_dir_all(&dir);
+        assert!(found);
+        assert!(found_new);
+    }
+
+    #[test]
+    fn polling_covers_subdirectories() {
+        let dir = env::temp_dir().join(format!("gl_sandpit_watcher_poll_{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("lib")).unwrap();
+
+        let mut source = Source::poll(&dir);
+        fs::write(dir.join("lib/shared.glsl"), "1").unwrap();
+        let changed = source.changes(&dir);
+        let unchanged = source.changes(&dir);
+
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(changed, vec![dir.join("lib/shared.glsl")]);
+        assert!(unchanged.is_empty());
+    }
+}